@@ -0,0 +1,538 @@
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    io::{Io, StdIo},
+    language::{
+        token::Position,
+        types::{Context, FreeVar, TType, TypeFunc, NUMERIC},
+    },
+    message::{Content, Message, Severity},
+    phase::interpreter::Value,
+};
+
+/// A function implemented in Rust, called once it has been given all of its arguments.
+/// When it can't give a result it says why. A message without a source path of its own, like
+/// the ones `error` makes, is reported at the call.
+pub type Function = dyn Fn(&[Value]) -> Result<Value, Box<Message>>;
+
+/// A function that is part of the language rather than defined by a program.
+#[derive(Clone)]
+pub struct Builtin {
+    /// The type of the function, with its type variables quantified
+    pub scheme: TType,
+    /// How many arguments the function takes, one for each arrow of its type
+    pub arity: usize,
+    pub function: Rc<Function>,
+}
+
+/// The built-in functions by name. The type checker takes their types from here and the
+/// interpreter calls them from here, so a function only has to be registered once.
+/// `Builtins::default()` has the ones every program can use, and more can be registered on top.
+#[derive(Clone)]
+pub struct Builtins(HashMap<String, Builtin>);
+
+impl Builtins {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// Add a function, replacing any other with the same name. The type variables of `t`
+    /// stand for any type.
+    pub fn register<F>(&mut self, name: &str, t: TType, function: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, Box<Message>> + 'static,
+    {
+        let mut arity = 0;
+        let mut output = &t;
+        while let TType::Application(TypeFunc::Func { output: o, .. }) = output {
+            arity += 1;
+            output = o;
+        }
+
+        let mut variables = t.free_variables().into_iter().collect::<Vec<String>>();
+        variables.sort();
+        let scheme = variables
+            .into_iter()
+            .rev()
+            .fold(t, |inner, variable| TType::Quantifier {
+                variable,
+                inner: Box::new(inner),
+            });
+
+        self.0.insert(
+            name.to_string(),
+            Builtin {
+                scheme,
+                arity,
+                function: Rc::new(function),
+            },
+        );
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.0.get(name)
+    }
+
+    /// The types of all the functions.
+    pub fn types(&self) -> Context {
+        self.0
+            .iter()
+            .map(|(name, builtin)| (name.clone(), builtin.scheme.clone()))
+            .collect()
+    }
+}
+
+/// An error that doesn't point anywhere, so the interpreter reports it at the call.
+pub fn error(message: impl Into<String>) -> Box<Message> {
+    Box::new(Message {
+        severity: Severity::Error,
+        position: Position {
+            line: 0,
+            column: 0,
+            begin: 0,
+            end: 0,
+        },
+        content: Content {
+            message: message.into(),
+            indicator_message: Some(" here".to_string()),
+            fix_hint: None,
+        },
+        source_path: PathBuf::new(),
+    })
+}
+
+/// The error for arguments the type checker should have ruled out.
+fn unexpected(name: &str, arguments: &[Value]) -> Box<Message> {
+    error(format!(
+        "cannot call '{}' with {}",
+        name,
+        arguments
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    ))
+}
+
+/// Arithmetic on ints is checked, it is an error for the result to not fit in an int.
+fn arithmetic(op: &str, arguments: &[Value]) -> Result<Value, Box<Message>> {
+    match arguments {
+        [Value::Int(_), Value::Int(0)] if op == "/" || op == "%" => Err(error("division by zero")),
+        [Value::Int(n1), Value::Int(n2)] => {
+            let result = match op {
+                "+" => n1.checked_add(*n2),
+                "-" => n1.checked_sub(*n2),
+                "*" => n1.checked_mul(*n2),
+                "/" => n1.checked_div(*n2),
+                _ => n1.checked_rem(*n2),
+            };
+            result
+                .map(Value::Int)
+                .ok_or_else(|| error(format!("{} {} {} does not fit in an int", n1, op, n2)))
+        }
+        [Value::Float(n1), Value::Float(n2)] => Ok(Value::Float(match op {
+            "+" => n1 + n2,
+            "-" => n1 - n2,
+            "*" => n1 * n2,
+            "/" => n1 / n2,
+            _ => n1 % n2,
+        })),
+        _ => Err(unexpected(op, arguments)),
+    }
+}
+
+fn compare(op: &str, arguments: &[Value]) -> Result<Value, Box<Message>> {
+    let ordering = match arguments {
+        [Value::Int(n1), Value::Int(n2)] => n1.partial_cmp(n2),
+        [Value::Float(n1), Value::Float(n2)] => n1.partial_cmp(n2),
+        _ => return Err(unexpected(op, arguments)),
+    };
+
+    // Comparisons with NaN are always false
+    Ok(Value::Bool(matches!(
+        (op, ordering),
+        ("<", Some(Ordering::Less))
+            | ("<=", Some(Ordering::Less | Ordering::Equal))
+            | (">", Some(Ordering::Greater))
+            | (">=", Some(Ordering::Greater | Ordering::Equal))
+    )))
+}
+
+impl Default for Builtins {
+    fn default() -> Self {
+        Builtins::with_io(Rc::new(RefCell::new(StdIo)))
+    }
+}
+
+impl Builtins {
+    /// The functions every program can use, doing their I/O through `io`.
+    pub fn with_io(io: Rc<RefCell<dyn Io>>) -> Self {
+        let int = || TType::Application(TypeFunc::Int);
+        let float = || TType::Application(TypeFunc::Float);
+        let bool = || TType::Application(TypeFunc::Bool);
+        let string = || TType::Application(TypeFunc::String);
+        let a = || TType::Variable("a".to_string());
+        let list = || TType::Application(TypeFunc::List(Box::new(a())));
+        let unit = || TType::Application(TypeFunc::Unit);
+        let number = || TType::Variable(NUMERIC.to_string());
+
+        let mut builtins = Builtins::new();
+        // Operators are named by their symbol. The interpreter only calls `&&` and `||` when
+        // they are used as functions, otherwise it leaves out their right side when it can.
+        for op in ["+", "-", "*", "/", "%"] {
+            builtins.register(
+                op,
+                TType::function(number(), TType::function(number(), number())),
+                move |arguments| arithmetic(op, arguments),
+            );
+        }
+        for op in ["<", "<=", ">", ">="] {
+            builtins.register(
+                op,
+                TType::function(number(), TType::function(number(), bool())),
+                move |arguments| compare(op, arguments),
+            );
+        }
+        builtins.register(
+            "&&",
+            TType::function(bool(), TType::function(bool(), bool())),
+            |arguments| match arguments {
+                [Value::Bool(b1), Value::Bool(b2)] => Ok(Value::Bool(*b1 && *b2)),
+                _ => Err(unexpected("&&", arguments)),
+            },
+        );
+        builtins.register(
+            "||",
+            TType::function(bool(), TType::function(bool(), bool())),
+            |arguments| match arguments {
+                [Value::Bool(b1), Value::Bool(b2)] => Ok(Value::Bool(*b1 || *b2)),
+                _ => Err(unexpected("||", arguments)),
+            },
+        );
+        builtins.register(
+            "==",
+            TType::function(a(), TType::function(a(), bool())),
+            |arguments| match arguments {
+                [v1, v2] => Ok(Value::Bool(v1.equals(v2).map_err(error)?)),
+                _ => Err(unexpected("==", arguments)),
+            },
+        );
+        builtins.register(
+            "!=",
+            TType::function(a(), TType::function(a(), bool())),
+            |arguments| match arguments {
+                [v1, v2] => Ok(Value::Bool(!v1.equals(v2).map_err(error)?)),
+                _ => Err(unexpected("!=", arguments)),
+            },
+        );
+        builtins.register(
+            "++",
+            TType::function(string(), TType::function(string(), string())),
+            |arguments| match arguments {
+                [Value::String(s1), Value::String(s2)] => {
+                    Ok(Value::String(format!("{}{}", s1, s2)))
+                }
+                _ => Err(unexpected("++", arguments)),
+            },
+        );
+
+        // Stops the program with a message showing the value it is given
+        builtins.register(
+            "panic",
+            TType::function(a(), TType::Variable("b".to_string())),
+            |arguments| match arguments {
+                [v] => Err(error(format!("panicked with {}", v))),
+                _ => Err(unexpected("panic", arguments)),
+            },
+        );
+        builtins.register(
+            "string_length",
+            TType::function(string(), int()),
+            |arguments| match arguments {
+                [Value::String(s)] => Ok(Value::Int(s.chars().count() as i64)),
+                _ => Err(unexpected("string_length", arguments)),
+            },
+        );
+        builtins.register(
+            "substring",
+            TType::function(
+                string(),
+                TType::function(int(), TType::function(int(), string())),
+            ),
+            |arguments| match arguments {
+                [Value::String(s), Value::Int(start), Value::Int(end)] => {
+                    let length = s.chars().count();
+                    if *start < 0 || start > end || *end > length as i64 {
+                        return Err(error(format!(
+                            "cannot take characters {} to {} of a string with {} characters",
+                            start, end, length
+                        )));
+                    }
+
+                    let (start, end) = (*start as usize, *end as usize);
+                    Ok(Value::String(
+                        s.chars().skip(start).take(end - start).collect(),
+                    ))
+                }
+                _ => Err(unexpected("substring", arguments)),
+            },
+        );
+        builtins.register(
+            "int_to_float",
+            TType::function(int(), float()),
+            |arguments| match arguments {
+                [Value::Int(n)] => Ok(Value::Float(*n as f64)),
+                _ => Err(unexpected("int_to_float", arguments)),
+            },
+        );
+        // Rounds towards zero
+        builtins.register(
+            "float_to_int",
+            TType::function(float(), int()),
+            |arguments| match arguments {
+                [Value::Float(n)]
+                    if n.is_finite() && *n >= i64::MIN as f64 && *n < i64::MAX as f64 =>
+                {
+                    Ok(Value::Int(n.trunc() as i64))
+                }
+                [Value::Float(n)] => Err(error(format!("{:?} does not fit in an int", n))),
+                _ => Err(unexpected("float_to_int", arguments)),
+            },
+        );
+        builtins.register(
+            "int_to_string",
+            TType::function(int(), string()),
+            |arguments| match arguments {
+                [n @ Value::Int(_)] => Ok(Value::String(n.to_string())),
+                _ => Err(unexpected("int_to_string", arguments)),
+            },
+        );
+        builtins.register(
+            "float_to_string",
+            TType::function(float(), string()),
+            |arguments| match arguments {
+                [n @ Value::Float(_)] => Ok(Value::String(n.to_string())),
+                _ => Err(unexpected("float_to_string", arguments)),
+            },
+        );
+        builtins.register(
+            "string_to_int",
+            TType::function(string(), int()),
+            |arguments| match arguments {
+                [Value::String(s)] => s
+                    .trim()
+                    .parse::<i64>()
+                    .map(Value::Int)
+                    .map_err(|_| error(format!("cannot convert {:?} to an int", s))),
+                _ => Err(unexpected("string_to_int", arguments)),
+            },
+        );
+        builtins.register(
+            "string_to_float",
+            TType::function(string(), float()),
+            |arguments| match arguments {
+                [Value::String(s)] => s
+                    .trim()
+                    .parse::<f64>()
+                    .map(Value::Float)
+                    .map_err(|_| error(format!("cannot convert {:?} to a float", s))),
+                _ => Err(unexpected("string_to_float", arguments)),
+            },
+        );
+        builtins.register(
+            "cons",
+            TType::function(a(), TType::function(list(), list())),
+            |arguments| match arguments {
+                [head, Value::List(tail)] => Ok(Value::List(tail.cons(head.clone()))),
+                _ => Err(unexpected("cons", arguments)),
+            },
+        );
+        builtins.register(
+            "head",
+            TType::function(list(), a()),
+            |arguments| match arguments {
+                [Value::List(list)] => match list.split_first() {
+                    Some((head, _)) => Ok(head.clone()),
+                    None => Err(error("cannot take the head of an empty list")),
+                },
+                _ => Err(unexpected("head", arguments)),
+            },
+        );
+        builtins.register(
+            "tail",
+            TType::function(list(), list()),
+            |arguments| match arguments {
+                [Value::List(list)] => match list.split_first() {
+                    Some((_, tail)) => Ok(Value::List(tail.clone())),
+                    None => Err(error("cannot take the tail of an empty list")),
+                },
+                _ => Err(unexpected("tail", arguments)),
+            },
+        );
+        builtins.register(
+            "empty",
+            TType::function(list(), bool()),
+            |arguments| match arguments {
+                [Value::List(list)] => Ok(Value::Bool(list.split_first().is_none())),
+                _ => Err(unexpected("empty", arguments)),
+            },
+        );
+
+        // Values are printed the way they are spliced into strings
+        let out = io.clone();
+        builtins.register(
+            "print",
+            TType::function(a(), unit()),
+            move |arguments| match arguments {
+                [v] => {
+                    out.borrow_mut().print(&v.to_string());
+                    Ok(Value::Unit)
+                }
+                _ => Err(unexpected("print", arguments)),
+            },
+        );
+        let out = io.clone();
+        builtins.register(
+            "println",
+            TType::function(a(), unit()),
+            move |arguments| match arguments {
+                [v] => {
+                    out.borrow_mut().print(&format!("{}\n", v));
+                    Ok(Value::Unit)
+                }
+                _ => Err(unexpected("println", arguments)),
+            },
+        );
+        let input = io.clone();
+        builtins.register(
+            "read_line",
+            TType::function(unit(), string()),
+            move |arguments| match arguments {
+                [Value::Unit] => match input.borrow_mut().read_line() {
+                    Some(line) => Ok(Value::String(line)),
+                    None => Err(error("there is no more input to read")),
+                },
+                _ => Err(unexpected("read_line", arguments)),
+            },
+        );
+        let files = io.clone();
+        builtins.register(
+            "read_file",
+            TType::function(string(), string()),
+            move |arguments| match arguments {
+                [Value::String(path)] => match files.borrow_mut().read_file(Path::new(path)) {
+                    Ok(contents) => Ok(Value::String(contents)),
+                    Err(e) => Err(error(format!("cannot read '{}': {}", path, e))),
+                },
+                _ => Err(unexpected("read_file", arguments)),
+            },
+        );
+        builtins.register(
+            "write_file",
+            TType::function(string(), TType::function(string(), unit())),
+            move |arguments| match arguments {
+                [Value::String(path), Value::String(contents)] => {
+                    match io.borrow_mut().write_file(Path::new(path), contents) {
+                        Ok(()) => Ok(Value::Unit),
+                        Err(e) => Err(error(format!("cannot write '{}': {}", path, e))),
+                    }
+                }
+                _ => Err(unexpected("write_file", arguments)),
+            },
+        );
+
+        builtins
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Builtins;
+    use crate::phase::interpreter::Value;
+
+    /// Call one of the default functions.
+    fn call(name: &str, arguments: &[Value]) -> Result<Value, String> {
+        let builtins = Builtins::default();
+        (builtins.get(name).unwrap().function)(arguments).map_err(|e| e.content.message)
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn strings_are_measured_and_cut_in_characters() {
+        assert_eq!(call("string_length", &[string("hé!")]), Ok(Value::Int(3)));
+        assert_eq!(
+            call(
+                "substring",
+                &[string("héllo"), Value::Int(1), Value::Int(3)]
+            ),
+            Ok(string("él"))
+        );
+        assert_eq!(
+            call("substring", &[string("hé"), Value::Int(1), Value::Int(3)]),
+            Err("cannot take characters 1 to 3 of a string with 2 characters".to_string())
+        );
+    }
+
+    #[test]
+    fn strings_convert_to_and_from_numbers() {
+        assert_eq!(call("int_to_string", &[Value::Int(-7)]), Ok(string("-7")));
+        assert_eq!(call("string_to_int", &[string(" 42 ")]), Ok(Value::Int(42)));
+        assert_eq!(
+            call("string_to_float", &[string("2.5")]),
+            Ok(Value::Float(2.5))
+        );
+        assert_eq!(
+            call("string_to_int", &[string("4x")]),
+            Err("cannot convert \"4x\" to an int".to_string())
+        );
+    }
+
+    #[test]
+    fn floats_become_ints_towards_zero_when_they_fit() {
+        assert_eq!(
+            call("float_to_int", &[Value::Float(-2.7)]),
+            Ok(Value::Int(-2))
+        );
+        assert_eq!(
+            call("int_to_float", &[Value::Int(3)]),
+            Ok(Value::Float(3.0))
+        );
+        assert_eq!(
+            call("float_to_int", &[Value::Float(1e19)]),
+            Err("1e19 does not fit in an int".to_string())
+        );
+    }
+
+    #[test]
+    fn operators_are_called_by_their_symbol() {
+        assert_eq!(
+            call("+", &[Value::Int(2), Value::Int(3)]),
+            Ok(Value::Int(5))
+        );
+        assert_eq!(
+            call("<=", &[Value::Float(f64::NAN), Value::Float(1.0)]),
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            call("!=", &[string("a"), string("b")]),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            call("%", &[Value::Int(1), Value::Int(0)]),
+            Err("division by zero".to_string())
+        );
+        assert_eq!(
+            call("*", &[Value::Int(i64::MAX), Value::Int(2)]),
+            Err(format!("{} * 2 does not fit in an int", i64::MAX))
+        );
+    }
+}
@@ -1,7 +1,15 @@
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct Config {
     pub resilient: bool,
     pub display_errors: bool,
+    /// Whether the prelude is loaded along with the program
+    pub prelude: bool,
+    /// Whether the syntax tree, the AST and the type of `main` are written to stderr
+    pub dump: bool,
+    /// The source with the `main` function that is run
+    pub entry_point: PathBuf,
 }
 
 impl Default for Config {
@@ -9,6 +17,9 @@ impl Default for Config {
         Self {
             resilient: true,
             display_errors: false,
+            prelude: true,
+            dump: false,
+            entry_point: PathBuf::from("./main.sfl"),
         }
     }
 }
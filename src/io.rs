@@ -0,0 +1,47 @@
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+};
+
+/// Everything a program can do to the world outside of it. The built-in I/O functions go
+/// through this, so the console and the filesystem can be swapped out, like in tests.
+pub trait Io {
+    /// Write text to the output, as it is
+    fn print(&mut self, text: &str);
+    /// The next line of the input without its line ending, or nothing at the end of the input
+    fn read_line(&mut self) -> Option<String>;
+    fn read_file(&mut self, path: &Path) -> std::io::Result<String>;
+    fn write_file(&mut self, path: &Path, contents: &str) -> std::io::Result<()>;
+}
+
+/// The console and the filesystem of the machine the interpreter runs on.
+pub struct StdIo;
+
+impl Io for StdIo {
+    fn print(&mut self, text: &str) {
+        let mut stdout = std::io::stdout();
+        // A closed stdout has nowhere left to report the failure to
+        let _ = stdout.write_all(text.as_bytes());
+        let _ = stdout.flush();
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match std::io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                let end = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(end);
+                Some(line)
+            }
+        }
+    }
+
+    fn read_file(&mut self, path: &Path) -> std::io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+
+    fn write_file(&mut self, path: &Path, contents: &str) -> std::io::Result<()> {
+        std::fs::write(path, contents)
+    }
+}
@@ -1,19 +1,319 @@
-use std::fmt::Debug;
+use std::{
+    collections::HashSet,
+    fmt::{Debug, Display},
+};
 
-use crate::language::token::Token;
+use crate::language::token::{Token, TokenKind};
 
 use super::token::Position;
 
+/// A whole source file, the ordered definitions and data types it contains.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Program {
+    pub definitions: Vec<Definition>,
+    pub types: Vec<TypeDefinition>,
+}
+
+impl Program {
+    pub fn get(&self, name: &str) -> Option<&Definition> {
+        self.definitions.iter().find(|d| d.name.text() == name)
+    }
+
+    pub fn pretty_print(&self) -> String {
+        self.types
+            .iter()
+            .map(|t| t.pretty_print())
+            .chain(self.definitions.iter().map(|d| d.pretty_print()))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// A data type, `type Name(params) = Constructor(fields) | ...`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TypeDefinition {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub constructors: Vec<Constructor>,
+}
+
+impl TypeDefinition {
+    pub fn pretty_print(&self) -> String {
+        let params = self
+            .params
+            .iter()
+            .map(|p| format!(" '{}'", p.text()))
+            .collect::<String>();
+        let constructors = self
+            .constructors
+            .iter()
+            .map(|c| {
+                let fields = c
+                    .fields
+                    .iter()
+                    .map(|f| format!(" '{}'", f))
+                    .collect::<String>();
+                format!("\n  Constructor '{}'{}", c.name.text(), fields)
+            })
+            .collect::<String>();
+
+        format!(
+            "Type '{}'\n  Params{}{}",
+            self.name.text(),
+            params,
+            constructors
+        )
+    }
+}
+
+/// One of the ways to make a value of a data type, like `Some(a)` in `type Option(a) = None | Some(a)`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Constructor {
+    pub name: Token,
+    pub fields: Vec<TypeAnnotation>,
+}
+
+/// A top level function, `def name(params): return_type { body }`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Definition {
+    pub name: Token,
+    pub params: Vec<Param>,
+    pub return_type: Option<TypeAnnotation>,
+    pub body: Ast,
+    /// The whole definition, from `def` to the end of its body.
+    pub position: Position,
+}
+
+impl Definition {
+    /// The definition as a single value.
+    /// Parameters become nested abstractions, so `def f(a, b) { e }` is `\a -> \b -> e`,
+    /// and a definition without any takes `()`, so `def f() { e }` is `\_: () -> e`.
+    pub fn value(&self) -> Ast {
+        if self.params.is_empty() {
+            let token = |kind| Token {
+                kind,
+                ..self.name.clone()
+            };
+            return Ast::Abstraction(
+                Pattern::Wildcard(token(TokenKind::Name("_".to_string()))),
+                Some(TypeAnnotation::Unit(token(TokenKind::ParenL))),
+                Box::new(self.body.clone()),
+            );
+        }
+
+        self.params
+            .iter()
+            .rev()
+            .fold(self.body.clone(), |body, param| {
+                Ast::Abstraction(
+                    param.pattern.clone(),
+                    param.annotation.clone(),
+                    Box::new(body),
+                )
+            })
+    }
+
+    pub fn pretty_print(&self) -> String {
+        let params = self
+            .params
+            .iter()
+            .map(|p| match &p.annotation {
+                Some(t) => format!(" '{}: {}'", p.pattern, t),
+                None => format!(" '{}'", p.pattern),
+            })
+            .collect::<String>();
+        let return_type = self
+            .return_type
+            .as_ref()
+            .map_or(String::new(), |t| format!(": '{}'", t));
+
+        format!(
+            "Definition '{}'\n  Params{}{}\n{}",
+            self.name.text(),
+            params,
+            return_type,
+            self.body.print(1)
+        )
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Param {
+    pub pattern: Pattern,
+    pub annotation: Option<TypeAnnotation>,
+}
+
+/// A type as it is written in the source, like the `number` in `a: number`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum TypeAnnotation {
+    /// A named type like `int`, or a type variable like `a`
+    Name(Token),
+    /// A data type given type arguments, like `Option(int)`
+    Application(Token, Vec<TypeAnnotation>),
+    Function(Box<TypeAnnotation>, Box<TypeAnnotation>),
+    /// A tuple type like `(int, string)`
+    Tuple(Vec<TypeAnnotation>),
+    /// A record type like `{ age: int | r }`, where `r` stands for any other fields
+    Record(Vec<(Token, TypeAnnotation)>, Option<Token>),
+    /// `()`, the token is the `(`
+    Unit(Token),
+}
+
+impl TypeAnnotation {
+    pub fn position(&self) -> Position {
+        match self {
+            TypeAnnotation::Name(t)
+            | TypeAnnotation::Application(t, _)
+            | TypeAnnotation::Unit(t) => t.position,
+            TypeAnnotation::Function(input, output) => input.position().to(output.position()),
+            TypeAnnotation::Tuple(elements) => match (elements.first(), elements.last()) {
+                (Some(first), Some(last)) => first.position().to(last.position()),
+                _ => Ast::Err.position(),
+            },
+            TypeAnnotation::Record(fields, rest) => {
+                match (
+                    fields.first(),
+                    rest.as_ref().or(fields.last().map(|(t, _)| t)),
+                ) {
+                    (Some((first, _)), Some(last)) => first.position.to(last.position),
+                    (None, Some(rest)) => rest.position,
+                    _ => Ast::Err.position(),
+                }
+            }
+        }
+    }
+}
+
+impl Display for TypeAnnotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeAnnotation::Name(t) => write!(f, "{}", t.text()),
+            TypeAnnotation::Unit(_) => write!(f, "()"),
+            TypeAnnotation::Application(t, arguments) => write!(
+                f,
+                "{}({})",
+                t.text(),
+                arguments
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            TypeAnnotation::Function(input, output) => match **input {
+                TypeAnnotation::Function(_, _) => write!(f, "({}) -> {}", input, output),
+                _ => write!(f, "{} -> {}", input, output),
+            },
+            TypeAnnotation::Tuple(elements) => write!(
+                f,
+                "({})",
+                elements
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            TypeAnnotation::Record(fields, rest) => {
+                let fields = fields
+                    .iter()
+                    .map(|(label, field)| format!("{}: {}", label.text(), field))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                match (fields.is_empty(), rest) {
+                    (true, None) => write!(f, "{{}}"),
+                    (true, Some(rest)) => write!(f, "{{ | {} }}", rest.text()),
+                    (false, None) => write!(f, "{{ {} }}", fields),
+                    (false, Some(rest)) => write!(f, "{{ {} | {} }}", fields, rest.text()),
+                }
+            }
+        }
+    }
+}
+
+/// What a value is matched against, like the `(q, r)` in `let (q, r) = divmod(a, b);`
+/// or the `Some(0)` in a `match` arm.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Pattern {
+    /// Binds the whole value to a name
+    Name(Token),
+    /// `_`, matches anything without binding it
+    Wildcard(Token),
+    /// Matches only a value equal to the literal
+    Literal(Token),
+    /// Matches each element of a tuple, the position spans the parentheses
+    Tuple(Vec<Pattern>, Position),
+    /// Matches the values made by a constructor and then each of their fields,
+    /// the position spans the whole pattern
+    Constructor(Token, Vec<Pattern>, Position),
+}
+
+impl Pattern {
+    /// The names the pattern binds, in the order they appear.
+    pub fn names(&self) -> Vec<Token> {
+        match self {
+            Pattern::Name(t) => vec![t.clone()],
+            Pattern::Wildcard(_) | Pattern::Literal(_) => Vec::new(),
+            Pattern::Tuple(elements, _) | Pattern::Constructor(_, elements, _) => {
+                elements.iter().flat_map(Pattern::names).collect()
+            }
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        match self {
+            Pattern::Name(t) | Pattern::Wildcard(t) | Pattern::Literal(t) => t.position,
+            Pattern::Tuple(_, position) | Pattern::Constructor(_, _, position) => *position,
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |elements: &[Pattern]| {
+            elements
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        match self {
+            Pattern::Name(t) | Pattern::Wildcard(t) | Pattern::Literal(t) => {
+                write!(f, "{}", t.text())
+            }
+            Pattern::Tuple(elements, _) => write!(f, "({})", list(elements)),
+            Pattern::Constructor(t, fields, _) if fields.is_empty() => write!(f, "{}", t.text()),
+            Pattern::Constructor(t, fields, _) => write!(f, "{}({})", t.text(), list(fields)),
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub enum Ast {
     Err,
     Expr(Box<Ast>),
-    Abstraction(Token, Box<Ast>),
+    Abstraction(Pattern, Option<TypeAnnotation>, Box<Ast>),
     Application(Box<Ast>, Box<Ast>),
     Literal(Token),
-    Let(Token, Box<Ast>, Box<Ast>),
+    Let(Pattern, Box<Ast>, Box<Ast>),
     Name(Token),
     BinaryOp(Token, Box<Ast>, Box<Ast>),
+    UnaryOp(Token, Box<Ast>),
+    If(Box<Ast>, Box<Ast>, Box<Ast>),
+    /// A string with expressions spliced into it, the text between them are string literals
+    Interpolation(Vec<Ast>),
+    Tuple(Vec<Ast>),
+    /// `()`, the value of expressions that are only there for their effect, like a `print`.
+    /// The token is the `(`
+    Unit(Token),
+    /// A list literal like `[1, 2, 3]`, the token is the `[`
+    List(Token, Vec<Ast>),
+    /// The value of the first arm whose pattern matches the value, the token is the `match`
+    Match(Token, Box<Ast>, Vec<(Pattern, Ast)>),
+    /// A record literal like `{ name: "x", age: 3 }`
+    Record(Vec<(Token, Ast)>),
+    /// A copy of a record with some fields replaced, like `{ r | age: 4 }`
+    RecordUpdate(Box<Ast>, Vec<(Token, Ast)>),
+    /// The value of a field of a record, like `r.age`
+    Field(Box<Ast>, Token),
 }
 
 impl Ast {
@@ -30,20 +330,36 @@ impl Ast {
         let kind_str = match self {
             Ast::Err => "Err",
             Ast::Expr(_) => "Expr",
-            Ast::Abstraction(_, _) => "Abstraction",
+            Ast::Abstraction(_, _, _) => "Abstraction",
             Ast::Application(_, _) => "Application",
             Ast::Literal(_) => "Literal",
             Ast::Let(_, _, _) => "Let",
             Ast::Name(_) => "Name",
             Ast::BinaryOp(_, _, _) => "BinaryOp",
+            Ast::UnaryOp(_, _) => "UnaryOp",
+            Ast::If(_, _, _) => "If",
+            Ast::Interpolation(_) => "Interpolation",
+            Ast::Tuple(_) => "Tuple",
+            Ast::Unit(_) => "Unit",
+            Ast::List(_, _) => "List",
+            Ast::Match(_, _, _) => "Match",
+            Ast::Record(_) => "Record",
+            Ast::RecordUpdate(_, _) => "RecordUpdate",
+            Ast::Field(_, _) => "Field",
         };
 
         let children_str = match self {
             Ast::Expr(e) => vec![e.print(level + 1)],
-            Ast::Abstraction(t, e) => vec![format!("{inset}  '{}'", t.text()), e.print(level + 1)],
+            Ast::Abstraction(t, annotation, e) => vec![
+                match annotation {
+                    Some(a) => format!("{inset}  '{}: {}'", t, a),
+                    None => format!("{inset}  '{}'", t),
+                },
+                e.print(level + 1),
+            ],
             Ast::Application(e1, e2) => vec![e1.print(level + 1), e2.print(level + 1)],
             Ast::Let(t, e1, e2) => vec![
-                format!("{inset}  '{}'", t.text()),
+                format!("{inset}  '{}'", t),
                 e1.print(level + 1),
                 e2.print(level + 1),
             ],
@@ -52,6 +368,25 @@ impl Ast {
                 format!("{inset}  '{}'", op.text()),
                 e2.print(level + 1),
             ],
+            Ast::UnaryOp(op, e) => vec![format!("{inset}  '{}'", op.text()), e.print(level + 1)],
+            Ast::If(c, t, e) => vec![c.print(level + 1), t.print(level + 1), e.print(level + 1)],
+            Ast::Interpolation(parts) | Ast::Tuple(parts) | Ast::List(_, parts) => {
+                parts.iter().map(|p| p.print(level + 1)).collect()
+            }
+            Ast::Match(_, e, arms) => {
+                let arms = arms.iter().flat_map(|(pattern, body)| {
+                    [format!("{inset}  '{}'", pattern), body.print(level + 1)]
+                });
+                [e.print(level + 1)].into_iter().chain(arms).collect()
+            }
+            Ast::Record(fields) => print_fields(fields, level),
+            Ast::RecordUpdate(e, fields) => [e.print(level + 1)]
+                .into_iter()
+                .chain(print_fields(fields, level))
+                .collect(),
+            Ast::Field(e, label) => {
+                vec![e.print(level + 1), format!("{inset}  '{}'", label.text())]
+            }
             _ => Vec::new(),
         }
         .join("\n");
@@ -59,13 +394,71 @@ impl Ast {
         format!("{inset}{kind_str}\n{children_str}")
     }
 
-    pub fn pretty_print(&self) -> String {
-        self.print(0)
+    /// The names this expression refers to that it doesn't bind itself.
+    pub fn free_names(&self) -> HashSet<String> {
+        match self {
+            Ast::Err | Ast::Literal(_) | Ast::Unit(_) => HashSet::new(),
+            Ast::Name(t) => HashSet::from([t.text()]),
+            Ast::Expr(e) => e.free_names(),
+            Ast::Abstraction(pattern, _, e) => {
+                let mut names = e.free_names();
+                for name in pattern.names() {
+                    names.remove(&name.text());
+                }
+                names
+            }
+            Ast::Let(pattern, e1, e2) => {
+                let mut names = e2.free_names();
+                for name in pattern.names() {
+                    names.remove(&name.text());
+                }
+                names.extend(e1.free_names());
+                names
+            }
+            Ast::Application(e1, e2) => {
+                let mut names = e1.free_names();
+                names.extend(e2.free_names());
+                names
+            }
+            Ast::BinaryOp(op, e1, e2) => {
+                let mut names = e1.free_names();
+                names.extend(e2.free_names());
+                names.insert(op.text());
+                names
+            }
+            Ast::UnaryOp(_, e) => e.free_names(),
+            Ast::If(c, t, e) => {
+                let mut names = c.free_names();
+                names.extend(t.free_names());
+                names.extend(e.free_names());
+                names
+            }
+            Ast::Interpolation(parts) | Ast::Tuple(parts) | Ast::List(_, parts) => {
+                parts.iter().flat_map(|p| p.free_names()).collect()
+            }
+            Ast::Match(_, e, arms) => {
+                let mut names = e.free_names();
+                for (pattern, body) in arms {
+                    let mut arm_names = body.free_names();
+                    for name in pattern.names() {
+                        arm_names.remove(&name.text());
+                    }
+                    names.extend(arm_names);
+                }
+                names
+            }
+            Ast::Record(fields) => fields.iter().flat_map(|(_, e)| e.free_names()).collect(),
+            Ast::RecordUpdate(e, fields) => {
+                let mut names = e.free_names();
+                names.extend(fields.iter().flat_map(|(_, e)| e.free_names()));
+                names
+            }
+            Ast::Field(e, _) => e.free_names(),
+        }
     }
-}
 
-impl Into<Position> for Ast {
-    fn into(self) -> Position {
+    /// Where to point when reporting something about this expression.
+    pub fn position(&self) -> Position {
         match self {
             Ast::Err => Position {
                 line: 0,
@@ -73,19 +466,32 @@ impl Into<Position> for Ast {
                 begin: 0,
                 end: 0,
             },
-            Ast::Expr(e) => e.as_ref().to_owned().into(),
-            Ast::Abstraction(_, e) => {
-                let e_pos: Position = e.as_ref().to_owned().into();
-                e_pos
-            }
-            Ast::Application(e1, _) => {
-                let e1_pos: Position = e1.as_ref().to_owned().into();
-                e1_pos
-            }
+            Ast::Expr(e) => e.position(),
+            Ast::Abstraction(_, _, e) => e.position(),
+            Ast::Application(e1, _) => e1.position(),
             Ast::Literal(t) => t.position,
-            Ast::Let(n, _, _) => n.position,
+            Ast::Let(pattern, _, _) => pattern.position(),
             Ast::Name(t) => t.position,
             Ast::BinaryOp(op, _, _) => op.position,
+            Ast::UnaryOp(op, _) => op.position,
+            Ast::If(c, _, _) => c.position(),
+            Ast::Interpolation(parts) | Ast::Tuple(parts) => {
+                parts.first().map_or(Ast::Err.position(), Ast::position)
+            }
+            Ast::Match(t, _, _) | Ast::List(t, _) | Ast::Unit(t) => t.position,
+            Ast::Record(fields) => fields
+                .first()
+                .map_or(Ast::Err.position(), |(t, _)| t.position),
+            Ast::RecordUpdate(e, _) => e.position(),
+            Ast::Field(_, label) => label.position,
         }
     }
 }
+
+fn print_fields(fields: &[(Token, Ast)], level: usize) -> Vec<String> {
+    let inset = "  ".repeat(level);
+    fields
+        .iter()
+        .flat_map(|(label, e)| [format!("{inset}  '{}'", label.text()), e.print(level + 1)])
+        .collect()
+}
@@ -14,13 +14,35 @@ pub enum TreeKind {
     Args,
     Arg,
     TypeExpr,
+    FunctionType,
+    TypeArgs,
+    TypeDefinition,
+    TypeParams,
+    Constructor,
     Literal,
+    Interpolation,
     Binary,
+    Unary,
     If,
     Let,
+    Lambda,
+    Tuple,
+    Unit,
+    List,
+    TupleType,
+    TuplePattern,
+    ConstructorPattern,
+    Match,
+    MatchArm,
+    Record,
+    RecordUpdate,
+    RecordField,
+    RecordType,
+    Field,
     Name,
     Block,
     Statement,
+    Fixity,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -22,51 +22,137 @@ impl Token {
 #[derive(PartialEq, PartialOrd, Clone, Debug)]
 pub enum TokenKind {
     // Values
-    LiteralNumber(f64),
+    LiteralInt(i64),
+    LiteralFloat(f64),
     LiteralBool(bool),
+    LiteralString(String),
+    /// The text of an interpolated string up to its first `{`
+    StringStart(String),
+    /// The text of an interpolated string between a `}` and the next `{`
+    StringMiddle(String),
+    /// The text of an interpolated string after its last `}`
+    StringEnd(String),
     Name(String),
 
     // Keywords
     KeywordIf,
     KeywordElse,
     KeywordLet,
+    KeywordIn,
     KeywordThen,
     KeywordDef,
+    KeywordType,
+    KeywordInfixl,
+    KeywordInfixr,
+    KeywordInfix,
+    KeywordMatch,
 
     // Operators
     Plus,
     Minus,
+    Star,
+    Slash,
+    Percent,
+    EqualEqual,
+    BangEqual,
+    Bang,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    AmpAmp,
+    PipePipe,
+    /// Any other operator, these are defined by the program itself
+    Operator(String),
 
     // Misc
     ParenL,
     ParenR,
     CurlyL,
     CurlyR,
+    SquareL,
+    SquareR,
     Arrow,
     Colon,
     Equal,
     Comma,
+    Dot,
     Semi,
+    Backslash,
+    Pipe,
 
     // Special
     Eof,
     Error(LexerError),
 }
 
+impl TokenKind {
+    /// Whether this token can be used as an infix operator.
+    pub fn is_operator(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Star
+                | TokenKind::Slash
+                | TokenKind::Percent
+                | TokenKind::EqualEqual
+                | TokenKind::BangEqual
+                | TokenKind::Less
+                | TokenKind::LessEqual
+                | TokenKind::Greater
+                | TokenKind::GreaterEqual
+                | TokenKind::AmpAmp
+                | TokenKind::PipePipe
+                | TokenKind::Operator(_)
+        )
+    }
+}
+
+/// The text of a string as it would be written in a string literal.
+fn escaped(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '{' | '}' => format!("\\{}", c),
+            '\'' => c.to_string(),
+            c => c.escape_debug().to_string(),
+        })
+        .collect()
+}
+
 impl Display for TokenKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
             "{}",
             match self {
-                TokenKind::LiteralNumber(i) => i.to_string(),
+                TokenKind::LiteralInt(i) => i.to_string(),
+                TokenKind::LiteralFloat(i) => format!("{:?}", i),
                 TokenKind::LiteralBool(i) => i.to_string(),
+                TokenKind::LiteralString(s) => format!("\"{}\"", escaped(s)),
+                TokenKind::StringStart(s) => format!("\"{}{{", escaped(s)),
+                TokenKind::StringMiddle(s) => format!("}}{}{{", escaped(s)),
+                TokenKind::StringEnd(s) => format!("}}{}\"", escaped(s)),
                 TokenKind::Name(i) => i.to_string(),
                 TokenKind::KeywordIf => "if".to_string(),
                 TokenKind::KeywordElse => "else".to_string(),
                 TokenKind::KeywordLet => "let".to_string(),
+                TokenKind::KeywordIn => "in".to_string(),
                 TokenKind::Plus => "+".to_string(),
                 TokenKind::Minus => "-".to_string(),
+                TokenKind::Star => "*".to_string(),
+                TokenKind::Slash => "/".to_string(),
+                TokenKind::Percent => "%".to_string(),
+                TokenKind::EqualEqual => "==".to_string(),
+                TokenKind::BangEqual => "!=".to_string(),
+                TokenKind::Bang => "!".to_string(),
+                TokenKind::Less => "<".to_string(),
+                TokenKind::LessEqual => "<=".to_string(),
+                TokenKind::Greater => ">".to_string(),
+                TokenKind::GreaterEqual => ">=".to_string(),
+                TokenKind::AmpAmp => "&&".to_string(),
+                TokenKind::PipePipe => "||".to_string(),
+                TokenKind::Operator(op) => op.to_string(),
                 TokenKind::ParenL => "(".to_string(),
                 TokenKind::ParenR => ")".to_string(),
                 TokenKind::Colon => ":".to_string(),
@@ -75,11 +161,21 @@ impl Display for TokenKind {
                 TokenKind::Eof => "<eof>".to_string(),
                 TokenKind::Error(_) => "<error>".to_string(),
                 TokenKind::KeywordDef => "def".to_string(),
+                TokenKind::KeywordType => "type".to_string(),
+                TokenKind::KeywordInfixl => "infixl".to_string(),
+                TokenKind::KeywordInfixr => "infixr".to_string(),
+                TokenKind::KeywordInfix => "infix".to_string(),
+                TokenKind::KeywordMatch => "match".to_string(),
                 TokenKind::CurlyL => "{".to_string(),
                 TokenKind::CurlyR => "}".to_string(),
+                TokenKind::SquareL => "[".to_string(),
+                TokenKind::SquareR => "]".to_string(),
                 TokenKind::Comma => ",".to_string(),
+                TokenKind::Dot => ".".to_string(),
                 TokenKind::Semi => ";".to_string(),
                 TokenKind::KeywordThen => "then".to_string(),
+                TokenKind::Backslash => "\\".to_string(),
+                TokenKind::Pipe => "|".to_string(),
             }
         )
     }
@@ -87,7 +183,7 @@ impl Display for TokenKind {
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
 pub struct Position {
-    /// The line at where the position starts. A position can only span one line
+    /// The line at where the position starts. Only a position made with `through` spans lines
     pub line: usize,
     /// The column where the position begins.
     pub column: usize,
@@ -97,17 +193,55 @@ pub struct Position {
     pub end: usize,
 }
 
+impl Position {
+    /// The position from the start of this one to the end of `end`.
+    /// Positions can't span lines, so if `end` is on another line this position is kept as is.
+    pub fn to(self, end: Position) -> Position {
+        if self.line == end.line {
+            Position {
+                end: end.end,
+                ..self
+            }
+        } else {
+            self
+        }
+    }
+
+    /// The position from the start of this one to the end of `end`, even if that is on another line.
+    pub fn through(self, end: Position) -> Position {
+        Position {
+            end: end.end,
+            ..self
+        }
+    }
+}
+
 impl TryFrom<&Token> for Message {
     type Error = ();
 
     fn try_from(token: &Token) -> Result<Self, Self::Error> {
         if let TokenKind::Error(err) = token.kind {
             let content = match err {
+                // The lexer has already reported where the number went wrong
+                LexerError::InvalidNumber => return Err(()),
                 LexerError::UnexpectedToken(c) => Content {
                     message: format!("Unexpected character '{}'", c),
                     indicator_message: Some("found here".to_string()),
                     fix_hint: None,
                 },
+                LexerError::UnterminatedString => Content {
+                    message: "this string is never closed".to_string(),
+                    indicator_message: Some("string starts here".to_string()),
+                    fix_hint: Some("end the string with a '\"' on the same line".to_string()),
+                },
+                LexerError::InvalidEscape(c) => Content {
+                    message: format!("unknown escape sequence '\\{}'", c),
+                    indicator_message: Some("in this string".to_string()),
+                    fix_hint: Some(
+                        "the escape sequences are \\n, \\t, \\\\, \\\", \\{, \\} and \\u{...}"
+                            .to_string(),
+                    ),
+                },
             };
 
             Ok(Message {
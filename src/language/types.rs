@@ -10,7 +10,30 @@ pub enum TypeFunc {
         output: Box<TType>,
     },
     Bool,
-    Number,
+    Int,
+    Float,
+    String,
+    /// The type of `()`, which is the only value of it
+    Unit,
+    /// A data type defined by the program, like `Option(int)`
+    Data {
+        name: String,
+        arguments: Vec<TType>,
+    },
+    /// A fixed number of values of any types, like `(int, string)`
+    Tuple(Vec<TType>),
+    /// Any number of values of the same type, like `List(int)`
+    List(Box<TType>),
+    /// A record with the fields of a row, like `{ name: string, age: int }`
+    Record(Box<TType>),
+    /// The row without any fields
+    RowEmpty,
+    /// The row with a `label` field in front of the fields of `rest`
+    RowExtend {
+        label: String,
+        field: Box<TType>,
+        rest: Box<TType>,
+    },
 }
 
 impl Display for TypeFunc {
@@ -21,12 +44,63 @@ impl Display for TypeFunc {
             match self {
                 TypeFunc::Func { input, output } => format!("{} -> {}", input, output),
                 TypeFunc::Bool => "bool".to_string(),
-                TypeFunc::Number => "int".to_string(),
+                TypeFunc::Int => "int".to_string(),
+                TypeFunc::Float => "float".to_string(),
+                TypeFunc::String => "string".to_string(),
+                TypeFunc::Unit => "()".to_string(),
+                TypeFunc::Data { name, arguments } if arguments.is_empty() => name.to_string(),
+                TypeFunc::Data { name, arguments } => format!(
+                    "{}({})",
+                    name,
+                    arguments
+                        .iter()
+                        .map(|a| a.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+                TypeFunc::Tuple(elements) => format!(
+                    "({})",
+                    elements
+                        .iter()
+                        .map(|e| e.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+                TypeFunc::List(element) => format!("List({})", element),
+                TypeFunc::Record(row) => format!("{{{}}}", display_row(row)),
+                TypeFunc::RowEmpty => "{}".to_string(),
+                TypeFunc::RowExtend { .. } => format!("{{{}}}", display_row_of(self)),
             }
         )
     }
 }
 
+fn display_row(row: &TType) -> String {
+    match row {
+        TType::Application(f) => display_row_of(f),
+        tail => format!(" | {} ", tail),
+    }
+}
+
+/// The fields of a row, ending in the variable for the rest of them if there is one
+fn display_row_of(row: &TypeFunc) -> String {
+    let mut fields = vec![];
+    let mut row = row;
+    loop {
+        match row {
+            TypeFunc::RowExtend { label, field, rest } => {
+                fields.push(format!("{}: {}", label, field));
+                match rest.as_ref() {
+                    TType::Application(f) => row = f,
+                    tail => return format!(" {} | {} ", fields.join(", "), tail),
+                }
+            }
+            _ if fields.is_empty() => return String::new(),
+            _ => return format!(" {} ", fields.join(", ")),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum TType {
     Variable(String),
@@ -35,6 +109,13 @@ pub enum TType {
 }
 
 impl TType {
+    pub fn function(input: TType, output: TType) -> TType {
+        TType::Application(TypeFunc::Func {
+            input: Box::new(input),
+            output: Box::new(output),
+        })
+    }
+
     pub fn contains(&self, other: &TType) -> bool {
         let TType::Variable(n) = other else {
             return false;
@@ -44,6 +125,13 @@ impl TType {
             TType::Quantifier { variable: _, inner } => inner.contains(other),
             TType::Application(f) => match f {
                 TypeFunc::Func { input, output } => input.contains(other) || output.contains(other),
+                TypeFunc::List(t) | TypeFunc::Record(t) => t.contains(other),
+                TypeFunc::RowExtend { field, rest, .. } => {
+                    field.contains(other) || rest.contains(other)
+                }
+                TypeFunc::Data { arguments, .. } | TypeFunc::Tuple(arguments) => {
+                    arguments.iter().any(|a| a.contains(other))
+                }
                 _ => false,
             },
         }
@@ -66,6 +154,16 @@ impl Display for TType {
 
 pub type Context = HashMap<String, TType>;
 
+/// A quantifier over a type variable with this name, or a name starting with it, only ranges
+/// over `int` and `float`, like the operands of `+`.
+pub const NUMERIC: &str = "number";
+
+/// Whether a quantified type variable only ranges over `int` and `float`.
+pub fn is_numeric(variable: &str) -> bool {
+    variable.starts_with(NUMERIC)
+}
+
+#[derive(Default)]
 pub struct Substitution(HashMap<String, TType>);
 
 impl Substitution {
@@ -74,7 +172,7 @@ impl Substitution {
     }
 
     pub fn from<T: IntoIterator<Item = (String, TType)>>(cases: T) -> Self {
-        Self(HashMap::from_iter::<T>(cases.into()))
+        Self(HashMap::from_iter::<T>(cases))
     }
 
     pub fn apply<T: Substitutable>(&self, v: &T) -> T {
@@ -110,9 +208,18 @@ impl FreeVar for TType {
                 TypeFunc::Func { input, output } => {
                     let mut ifvs = input.free_variables();
                     let ofvs = output.free_variables();
-                    ifvs.extend(ofvs.into_iter());
+                    ifvs.extend(ofvs);
                     ifvs
                 }
+                TypeFunc::Data { arguments, .. } | TypeFunc::Tuple(arguments) => {
+                    arguments.iter().flat_map(|a| a.free_variables()).collect()
+                }
+                TypeFunc::List(t) | TypeFunc::Record(t) => t.free_variables(),
+                TypeFunc::RowExtend { field, rest, .. } => {
+                    let mut fvs = field.free_variables();
+                    fvs.extend(rest.free_variables());
+                    fvs
+                }
                 _ => HashSet::new(),
             },
             TType::Quantifier { variable, inner } => inner
@@ -146,6 +253,26 @@ impl Substitutable for TType {
                     input: Box::new(input.apply(s)),
                     output: Box::new(output.apply(s)),
                 }),
+                TypeFunc::Data { name, arguments } => TType::Application(TypeFunc::Data {
+                    name: name.to_owned(),
+                    arguments: arguments.iter().map(|a| a.apply(s)).collect(),
+                }),
+                TypeFunc::Tuple(elements) => TType::Application(TypeFunc::Tuple(
+                    elements.iter().map(|e| e.apply(s)).collect(),
+                )),
+                TypeFunc::List(element) => {
+                    TType::Application(TypeFunc::List(Box::new(element.apply(s))))
+                }
+                TypeFunc::Record(row) => {
+                    TType::Application(TypeFunc::Record(Box::new(row.apply(s))))
+                }
+                TypeFunc::RowExtend { label, field, rest } => {
+                    TType::Application(TypeFunc::RowExtend {
+                        label: label.to_owned(),
+                        field: Box::new(field.apply(s)),
+                        rest: Box::new(rest.apply(s)),
+                    })
+                }
                 _ => self.clone(),
             },
             TType::Quantifier { variable, inner } => TType::Quantifier {
@@ -164,10 +291,36 @@ impl Substitutable for Context {
     }
 }
 
+/// Composition, `s2.apply(&s1)` is the substitution that first applies `s1` and then `s2`.
 impl Substitutable for Substitution {
     fn apply(&self, s: &Substitution) -> Self {
-        let mut new = self.0.clone();
-        new.extend(s.0.iter().map(|(k, v)| (k.to_owned(), v.apply(self))));
+        let mut new = s.0.clone();
+        new.extend(self.0.iter().map(|(k, v)| (k.to_owned(), v.apply(s))));
         Substitution::from(new)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Substitution, TType, TypeFunc};
+
+    fn var(name: &str) -> TType {
+        TType::Variable(name.to_string())
+    }
+
+    #[test]
+    fn composed_substitutions_apply_the_first_one_first() {
+        let int = TType::Application(TypeFunc::Int);
+        let string = TType::Application(TypeFunc::String);
+        let s1 = Substitution::from([("a".to_string(), var("b")), ("c".to_string(), int.clone())]);
+        let s2 = Substitution::from([("b".to_string(), int.clone()), ("c".to_string(), string)]);
+
+        let t = TType::function(var("a"), TType::function(var("b"), var("c")));
+        let composed = s2.apply(&s1);
+        assert_eq!(composed.apply(&t), s2.apply(&s1.apply(&t)));
+        assert_eq!(
+            composed.apply(&t),
+            TType::function(int.clone(), TType::function(int.clone(), int))
+        );
+    }
+}
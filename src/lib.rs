@@ -0,0 +1,7 @@
+pub mod builtins;
+pub mod config;
+pub mod io;
+pub mod language;
+pub mod message;
+pub mod phase;
+pub mod prelude;
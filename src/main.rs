@@ -1,21 +1,18 @@
-mod config;
-mod language;
-mod message;
-mod phase;
-
-use config::Config;
 use std::{collections::HashMap, path::PathBuf, process::ExitCode};
 
-use crate::{
-    message::Message,
+use sfl::{
+    builtins::Builtins,
+    config::Config,
+    message::{Message, Severity},
     phase::{
         ast_builder::AstBuilder,
-        interpreter::Interpreter,
+        interpreter::{self, Interpreter, Value},
         lexer::Lexer,
         parser::Parser,
-        phase::{Phase, PhaseResult},
         type_checker::TypeChecker,
+        Phase, PhaseResult,
     },
+    prelude,
 };
 
 #[derive(clap::Parser)]
@@ -29,21 +26,41 @@ struct Cli {
 #[derive(clap::Subcommand)]
 enum Commands {
     /// Runs the current SFL project
-    Run,
+    Run {
+        /// Leave out the prelude, so only the core language is available
+        #[arg(long)]
+        no_prelude: bool,
+        /// Show the syntax tree, the AST and the type of 'main' before running it
+        #[arg(long)]
+        dump: bool,
+    },
 }
 
 fn main() -> ExitCode {
     use clap::Parser;
     let cli = Cli::parse();
 
-    // TODO: Get config from clap
+    // TODO: Get the rest of the config from clap
     let config = Config::default();
 
     match &cli.command {
-        Commands::Run => match run(config) {
-            Ok(_) => ExitCode::SUCCESS,
-            Err(_) => ExitCode::FAILURE,
-        },
+        Commands::Run { no_prelude, dump } => {
+            let config = Config {
+                prelude: !no_prelude,
+                dump: *dump,
+                ..config
+            };
+            // Programs can nest deeply, more than the main thread has stack for
+            let result = std::thread::Builder::new()
+                .stack_size(interpreter::STACK_SIZE)
+                .spawn(move || run(config))
+                .expect("to be able to start the interpreter")
+                .join();
+            match result {
+                Ok(Ok(_)) => ExitCode::SUCCESS,
+                _ => ExitCode::FAILURE,
+            }
+        }
     }
 }
 
@@ -60,48 +77,59 @@ fn run(config: Config) -> Result<(), ()> {
     };
 
     let contents = std::fs::read_to_string(&entry_point).expect("Unable to read 'main.sfl'");
+    let config = Config {
+        entry_point: entry_point.clone(),
+        ..config
+    };
     let mut sources = HashMap::new();
     sources.insert(entry_point, contents);
+    prelude::add(&config, &mut sources);
 
+    // Resilient runs go on past errors to report as many as they can, but never run the program
+    let mut failed = false;
     let lexer_result = Lexer::new().run(&config, &sources);
-    let lexer_result = complete_phase(&sources, &config, lexer_result)?;
+    let lexer_result = complete_phase(&sources, &config, &mut failed, lexer_result)?;
 
     let parser_result = Parser::new().run(&config, &lexer_result);
-    let parser_result = complete_phase(&sources, &config, parser_result)?;
-
-    println!(
-        "{}",
-        parser_result
-            .get(&PathBuf::from("./main.sfl"))
-            .unwrap()
-            .pretty_print()
-    );
-
-    // let ast_result = AstBuilder::new().run(&config, &parser_result);
-    // let ast_result = complete_phase(&sources, &config, ast_result)?;
-
-    // println!(
-    //     "--- AST ------------\n{}",
-    //     ast_result
-    //         .get(&PathBuf::from("./main.sfl"))
-    //         .unwrap()
-    //         .pretty_print()
-    // );
-
-    // let typechecker_result = TypeChecker::new().run(&config, &ast_result);
-    // let typechecker_result = complete_phase(&sources, &config, typechecker_result)?;
-
-    // println!(
-    //     "\n--- TYPE -----------\n{:?}",
-    //     typechecker_result
-    //         .get(&PathBuf::from("./main.sfl"))
-    //         .unwrap()
-    // );
-
-    // let result = Interpreter::new().run(&config, &ast_result);
-    // let result = complete_phase(&sources, &config, result);
-
-    // println!("\n--- RESULT -----------\n{}", result.unwrap());
+    let parser_result = complete_phase(&sources, &config, &mut failed, parser_result)?;
+
+    if let Some(tree) = parser_result
+        .get(&config.entry_point)
+        .filter(|_| config.dump)
+    {
+        eprintln!("{}", tree.pretty_print());
+    }
+
+    let ast_result = AstBuilder::new().run(&config, &parser_result);
+    let ast_result = complete_phase(&sources, &config, &mut failed, ast_result)?;
+
+    if let Some(program) = ast_result.get(&config.entry_point).filter(|_| config.dump) {
+        eprintln!("--- AST ------------\n{}", program.pretty_print());
+    }
+
+    let builtins = Builtins::default();
+    let typechecker_result = TypeChecker::with_builtins(builtins.clone()).run(&config, &ast_result);
+    let typechecker_result = complete_phase(&sources, &config, &mut failed, typechecker_result)?;
+
+    if let Some(t) = typechecker_result
+        .get(&config.entry_point)
+        .and_then(|ctx| ctx.get("main"))
+        .filter(|_| config.dump)
+    {
+        eprintln!("\n--- TYPE -----------\n{}", t);
+    }
+
+    if failed {
+        return Err(());
+    }
+
+    let result = Interpreter::with_builtins(builtins).run(&config, &ast_result);
+    let result = complete_phase(&sources, &config, &mut failed, result)?;
+
+    // A program that only does I/O has nothing more to show
+    if result != Value::Unit {
+        println!("{}", result);
+    }
 
     Ok(())
 }
@@ -109,13 +137,16 @@ fn run(config: Config) -> Result<(), ()> {
 fn complete_phase<R>(
     sources: &HashMap<PathBuf, String>,
     config: &Config,
+    failed: &mut bool,
     phase_result: PhaseResult<R>,
 ) -> Result<R, ()> {
     match phase_result {
         PhaseResult::Ok(result) => Ok(result),
         PhaseResult::SoftErr(result, errors) => {
             println!("{}", Message::format_errors(sources, &errors));
-            if !config.resilient {
+            let only_warnings = errors.iter().all(|e| e.severity != Severity::Error);
+            *failed |= !only_warnings;
+            if !config.resilient && !only_warnings {
                 Err(())
             } else {
                 Ok(result)
@@ -5,9 +5,7 @@ use crate::language::token::Position;
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
 pub enum Severity {
-    #[allow(dead_code)]
     Hint,
-    #[allow(dead_code)]
     Warning,
     Error,
 }
@@ -28,7 +26,7 @@ pub struct Message {
 }
 
 impl Message {
-    pub fn format(self: &Self, source: &str) -> String {
+    pub fn format(&self, source: &str) -> String {
         let line_num_width = format!("{}", self.position.line).len();
         let inset = line_num_width + 3;
 
@@ -54,10 +52,8 @@ impl Message {
             self.position.column,
         );
 
-        let source_line = source
-            .lines()
-            .nth(self.position.line)
-            .expect("source to contain line from position");
+        // The end of the file can be on the empty line after a trailing newline
+        let source_line = source.lines().nth(self.position.line).unwrap_or_default();
         let line_num = format!("{} | ", self.position.line + 1)
             .bright_blue()
             .bold();
@@ -67,7 +63,7 @@ impl Message {
             .content
             .indicator_message
             .to_owned()
-            .unwrap_or(String::new());
+            .unwrap_or_default();
         let indicator = format!(
             "{}{}{}",
             " ".repeat(inset + self.position.column),
@@ -79,15 +75,12 @@ impl Message {
         format!("{message}\n{position}\n{source_line}\n{indicator}\n").to_string()
     }
 
-    pub fn format_errors(sources: &HashMap<PathBuf, String>, errors: &Vec<Self>) -> String {
+    pub fn format_errors(sources: &HashMap<PathBuf, String>, errors: &[Self]) -> String {
         errors
             .iter()
             .map(|error| {
-                error.format(
-                    sources
-                        .get(&error.source_path)
-                        .expect("source file to be present"),
-                )
+                // Messages about a source that isn't there show no line of it
+                error.format(sources.get(&error.source_path).map_or("", |s| s))
             })
             .collect::<Vec<String>>()
             .join("\n")
@@ -1,55 +1,692 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     language::{
-        ast::Ast,
-        cst::{Child, Tree, TreeKind::*},
+        ast::{self, Ast, Pattern, Program, TypeAnnotation},
+        cst::{Child, Tree, TreeKind, TreeKind::*},
+        token::{Position, Token, TokenKind},
     },
+    message::{Content, Message, Severity},
     phase::{Phase, PhaseResult},
 };
 
-pub struct AstBuilder;
+pub struct AstBuilder {
+    source_path: PathBuf,
+    errors: Vec<Message>,
+}
+
+impl AstBuilder {
+    fn new(path: &Path) -> Self {
+        Self {
+            source_path: path.to_path_buf(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, position: Position, message: &str, indicator_message: &str) -> Ast {
+        self.errors.push(Message {
+            severity: Severity::Error,
+            position,
+            content: Content {
+                message: message.to_string(),
+                indicator_message: Some(indicator_message.to_string()),
+                fix_hint: None,
+            },
+            source_path: self.source_path.clone(),
+        });
+        Ast::Err
+    }
+
+    fn build_file(&mut self, tree: &Tree) -> Program {
+        let definitions = trees(tree)
+            .into_iter()
+            .filter(|t| t.kind == Definition)
+            .filter_map(|t| self.build_definition(t))
+            .collect();
+        let types = trees(tree)
+            .into_iter()
+            .filter(|t| t.kind == TypeDefinition)
+            .filter_map(|t| self.build_type_definition(t))
+            .collect();
+
+        Program { definitions, types }
+    }
+
+    fn build_type_definition(&mut self, tree: &Tree) -> Option<ast::TypeDefinition> {
+        let name = name_of(tree)?;
+        let params = trees(tree)
+            .into_iter()
+            .find(|t| t.kind == TypeParams)
+            .map_or(Vec::new(), |t| {
+                trees(t).into_iter().filter_map(name_of).collect()
+            });
+        let constructors = trees(tree)
+            .into_iter()
+            .filter(|t| t.kind == Constructor)
+            .filter_map(|t| {
+                let name = name_of(t)?;
+                let fields = match trees(t).into_iter().find(|t| t.kind == TypeArgs) {
+                    Some(args) => self.build_type_args(args)?,
+                    None => Vec::new(),
+                };
+                Some(ast::Constructor { name, fields })
+            })
+            .collect();
+
+        Some(ast::TypeDefinition {
+            name,
+            params,
+            constructors,
+        })
+    }
+
+    fn build_definition(&mut self, tree: &Tree) -> Option<ast::Definition> {
+        let name = name_of(tree)?;
+        let params = trees(tree)
+            .into_iter()
+            .find(|t| t.kind == Params)
+            .map_or(Vec::new(), |t| self.build_params(t));
+        let return_type = trees(tree)
+            .into_iter()
+            .find(|t| is_type(t.kind))
+            .and_then(|t| self.build_type(t));
+
+        let Some(body) = trees(tree).into_iter().find(|t| t.kind == Block) else {
+            self.error(
+                name.position,
+                &format!("'{}' has no body", name.text()),
+                "defined here",
+            );
+            return None;
+        };
+
+        Some(ast::Definition {
+            position: first_position(tree).through(last_position(body).unwrap_or(name.position)),
+            name,
+            params,
+            return_type,
+            body: self.build(body),
+        })
+    }
+
+    fn build_params(&mut self, tree: &Tree) -> Vec<ast::Param> {
+        trees(tree)
+            .into_iter()
+            .filter(|t| t.kind == Param)
+            .filter_map(|t| {
+                let pattern = trees(t)
+                    .into_iter()
+                    .find(|t| matches!(t.kind, Name | TuplePattern | ConstructorPattern | Literal))
+                    .and_then(|t| self.build_pattern(t));
+                let Some(pattern) = pattern else {
+                    self.error(first_position(t), "expected a parameter name", "here");
+                    return None;
+                };
+                let annotation = trees(t)
+                    .into_iter()
+                    .find(|t| is_type(t.kind))
+                    .and_then(|t| self.build_type(t));
+
+                Some(ast::Param {
+                    pattern,
+                    annotation,
+                })
+            })
+            .collect()
+    }
+
+    /// The pattern a value is matched against.
+    /// Names that start with an uppercase letter are constructors, and `_` is a wildcard.
+    /// A single pattern in parentheses is just that pattern, `(a)` binds `a`.
+    fn build_pattern(&mut self, tree: &Tree) -> Option<Pattern> {
+        match tree.kind {
+            Name => {
+                let name = name_of(tree)?;
+                let text = name.text();
+                if text == "_" {
+                    Some(Pattern::Wildcard(name))
+                } else if text.starts_with(char::is_uppercase) {
+                    let position = name.position;
+                    Some(Pattern::Constructor(name, Vec::new(), position))
+                } else {
+                    Some(Pattern::Name(name))
+                }
+            }
+            Literal => match &tree.children[..] {
+                [Child::Token(t)] => Some(Pattern::Literal(t.clone())),
+                // A number with a minus in front is the negative number
+                [Child::Token(minus), Child::Token(t)] => {
+                    let kind = match t.kind {
+                        TokenKind::LiteralInt(n) => TokenKind::LiteralInt(-n),
+                        TokenKind::LiteralFloat(n) => TokenKind::LiteralFloat(-n),
+                        _ => return None,
+                    };
+                    Some(Pattern::Literal(Token {
+                        kind,
+                        position: minus.position.to(t.position),
+                        ..t.clone()
+                    }))
+                }
+                _ => None,
+            },
+            TuplePattern => {
+                let mut elements = trees(tree)
+                    .into_iter()
+                    .map(|t| self.build_pattern(t))
+                    .collect::<Option<Vec<Pattern>>>()?;
+                if elements.len() == 1 {
+                    return elements.pop();
+                }
+                Some(Pattern::Tuple(elements, span(tree)))
+            }
+            ConstructorPattern => {
+                let (name, fields) = trees(tree).split_first().map(|(n, f)| (*n, f.to_vec()))?;
+                let fields = fields
+                    .into_iter()
+                    .map(|t| self.build_pattern(t))
+                    .collect::<Option<Vec<Pattern>>>()?;
+                Some(Pattern::Constructor(name_of(name)?, fields, span(tree)))
+            }
+            _ => None,
+        }
+    }
+
+    fn build_type(&mut self, tree: &Tree) -> Option<TypeAnnotation> {
+        match (tree.kind, &trees(tree)[..]) {
+            (FunctionType, [input, output]) => Some(TypeAnnotation::Function(
+                Box::new(self.build_type(input)?),
+                Box::new(self.build_type(output)?),
+            )),
+            (TypeExpr, [t]) if t.kind != Name => self.build_type(t),
+            (TypeExpr, [t]) => name_of(t).map(TypeAnnotation::Name),
+            (TypeExpr, [t, args]) if args.kind == TypeArgs => Some(TypeAnnotation::Application(
+                name_of(t)?,
+                self.build_type_args(args)?,
+            )),
+            (Unit, _) => match tree.children.first() {
+                Some(Child::Token(t)) => Some(TypeAnnotation::Unit(t.clone())),
+                _ => None,
+            },
+            (TupleType, elements) => Some(TypeAnnotation::Tuple(
+                elements
+                    .iter()
+                    .map(|t| self.build_type(t))
+                    .collect::<Option<Vec<TypeAnnotation>>>()?,
+            )),
+            (RecordType, items) => {
+                let mut fields = Vec::new();
+                let mut rest = None;
+                for item in items {
+                    match (item.kind, &trees(item)[..]) {
+                        (RecordField, [label, field]) => {
+                            fields.push((name_of(label)?, self.build_type(field)?))
+                        }
+                        (Name, _) => rest = Some(name_of(item)?),
+                        // The parser has already reported fields without a type
+                        _ => return None,
+                    }
+                }
+                Some(TypeAnnotation::Record(fields, rest))
+            }
+            // The parser has already reported types that are missing entirely
+            (TypeExpr, []) => None,
+            _ => {
+                self.error(first_position(tree), "expected a type", "here");
+                None
+            }
+        }
+    }
+
+    fn build_type_args(&mut self, tree: &Tree) -> Option<Vec<TypeAnnotation>> {
+        trees(tree)
+            .into_iter()
+            .map(|t| self.build_type(t))
+            .collect()
+    }
+
+    fn build(&mut self, tree: &Tree) -> Ast {
+        match tree.kind {
+            // The parser has already reported these
+            ErrorTree => Ast::Err,
+            Expr => {
+                let [e] = trees(tree)[..] else {
+                    return Ast::Err;
+                };
+                Ast::Expr(Box::new(self.build(e)))
+            }
+            Literal => {
+                let Some(Child::Token(t)) = tree.children.first() else {
+                    return Ast::Err;
+                };
+                Ast::Literal(t.clone())
+            }
+            Interpolation => {
+                let parts = tree
+                    .children
+                    .iter()
+                    .filter_map(|child| match child {
+                        Child::Tree(e) => Some(self.build(e)),
+                        Child::Token(t) => match &t.kind {
+                            TokenKind::StringStart(s)
+                            | TokenKind::StringMiddle(s)
+                            | TokenKind::StringEnd(s)
+                                if !s.is_empty() =>
+                            {
+                                Some(Ast::Literal(Token {
+                                    kind: TokenKind::LiteralString(s.clone()),
+                                    ..t.clone()
+                                }))
+                            }
+                            _ => None,
+                        },
+                    })
+                    .collect();
+
+                Ast::Interpolation(parts)
+            }
+            Name => {
+                let Some(Child::Token(t)) = tree.children.first() else {
+                    return Ast::Err;
+                };
+                Ast::Name(t.clone())
+            }
+            Binary => {
+                let Child::Tree(ref e1) = tree.children[0] else {
+                    return Ast::Err;
+                };
+                let Child::Token(ref op) = tree.children[1] else {
+                    return Ast::Err;
+                };
+                let Some(Child::Tree(ref e2)) = tree.children.get(2) else {
+                    return Ast::Err;
+                };
+
+                Ast::BinaryOp(
+                    op.clone(),
+                    Box::new(self.build(e1)),
+                    Box::new(self.build(e2)),
+                )
+            }
+            Unary => {
+                let Some(Child::Token(op)) = tree.children.first() else {
+                    return Ast::Err;
+                };
+                let [e] = trees(tree)[..] else {
+                    return Ast::Err;
+                };
+
+                Ast::UnaryOp(op.clone(), Box::new(self.build(e)))
+            }
+            Call => {
+                // f(a, b) is sugar for f(a)(b), and f() is f(())
+                let [callee, args] = trees(tree)[..] else {
+                    return Ast::Err;
+                };
+                let callee = self.build(callee);
+                let arguments = trees(args)
+                    .into_iter()
+                    .filter_map(|arg| trees(arg).first().copied())
+                    .collect::<Vec<&Tree>>();
+                if arguments.is_empty() {
+                    let Some(Child::Token(paren)) = args.children.first() else {
+                        return Ast::Err;
+                    };
+                    return Ast::Application(Box::new(callee), Box::new(Ast::Unit(paren.clone())));
+                }
+                arguments.into_iter().fold(callee, |f, arg| {
+                    Ast::Application(Box::new(f), Box::new(self.build(arg)))
+                })
+            }
+            Lambda => {
+                // \a b -> e is sugar for \a -> \b -> e
+                let params = self.build_params(tree);
+                let Some(Child::Tree(body)) = tree.children.last() else {
+                    return Ast::Err;
+                };
+
+                let body = self.build(body);
+                params.into_iter().rev().fold(body, |body, param| {
+                    Ast::Abstraction(param.pattern, param.annotation, Box::new(body))
+                })
+            }
+            Let => {
+                let [binding, value, body] = trees(tree)[..] else {
+                    return Ast::Err;
+                };
+                let Some(pattern) = self.build_pattern(binding) else {
+                    return Ast::Err;
+                };
+
+                Ast::Let(
+                    pattern,
+                    Box::new(self.build(value)),
+                    Box::new(self.build(body)),
+                )
+            }
+            Tuple => Ast::Tuple(trees(tree).into_iter().map(|e| self.build(e)).collect()),
+            Unit => match tree.children.first() {
+                Some(Child::Token(t)) => Ast::Unit(t.clone()),
+                _ => Ast::Err,
+            },
+            List => {
+                let Some(Child::Token(bracket)) = tree.children.first() else {
+                    return Ast::Err;
+                };
+                let elements = trees(tree).into_iter().map(|e| self.build(e)).collect();
+                Ast::List(bracket.clone(), elements)
+            }
+            Match => {
+                let Some(Child::Token(keyword)) = tree.children.first() else {
+                    return Ast::Err;
+                };
+                let Some((value, arms)) = trees(tree).split_first().map(|(v, a)| (*v, a.to_vec()))
+                else {
+                    return Ast::Err;
+                };
+
+                let mut built = Vec::new();
+                for arm in arms {
+                    let [pattern, body] = trees(arm)[..] else {
+                        return Ast::Err;
+                    };
+                    let Some(pattern) = self.build_pattern(pattern) else {
+                        return Ast::Err;
+                    };
+                    built.push((pattern, self.build(body)));
+                }
+
+                Ast::Match(keyword.clone(), Box::new(self.build(value)), built)
+            }
+            Record => match self.build_fields(&trees(tree)) {
+                Some(fields) => Ast::Record(fields),
+                None => Ast::Err,
+            },
+            RecordUpdate => {
+                let Some((record, fields)) =
+                    trees(tree).split_first().map(|(r, f)| (*r, f.to_vec()))
+                else {
+                    return Ast::Err;
+                };
+                let Some(fields) = self.build_fields(&fields) else {
+                    return Ast::Err;
+                };
+
+                Ast::RecordUpdate(Box::new(self.build(record)), fields)
+            }
+            Field => {
+                let [record, label] = trees(tree)[..] else {
+                    return Ast::Err;
+                };
+                let Some(label) = name_of(label) else {
+                    return Ast::Err;
+                };
+
+                Ast::Field(Box::new(self.build(record)), label)
+            }
+            If => {
+                let [condition, then, otherwise] = trees(tree)[..] else {
+                    return Ast::Err;
+                };
 
-fn build(tree: &Tree) -> Ast {
-    match tree.kind {
-        ErrorTree => Ast::Err,
-        Expr => {
-            let Child::Tree(ref t) = tree.children[1] else {
-                return Ast::Err;
-            };
-            Ast::Expr(Box::new(build(&t)))
+                Ast::If(
+                    Box::new(self.build(condition)),
+                    Box::new(self.build(then)),
+                    Box::new(self.build(otherwise)),
+                )
+            }
+            Block => {
+                let items = trees(tree);
+                let Some((value, statements)) = items.split_last() else {
+                    return Ast::Err;
+                };
+                if value.kind == Statement {
+                    return Ast::Err;
+                }
+
+                let value = self.build(value);
+                statements.iter().rev().fold(value, |rest, statement| {
+                    self.build_statement(statement, rest)
+                })
+            }
+            File | Definition | Params | Param | Args | Arg | TypeExpr | FunctionType
+            | TypeArgs | TypeDefinition | TypeParams | Constructor | Statement | Fixity
+            | TupleType | TuplePattern | ConstructorPattern | MatchArm | RecordField
+            | RecordType => self.error(first_position(tree), "expected an expression", "here"),
         }
-        Binary => {
-            let Child::Tree(ref e1) = tree.children[0] else {
-                return Ast::Err;
-            };
-            let Child::Token(ref op) = tree.children[1] else {
-                return Ast::Err;
-            };
-            let Child::Tree(ref e2) = tree.children[2] else {
-                return Ast::Err;
-            };
-
-            Ast::BinaryOp(op.clone(), Box::new(build(e1)), Box::new(build(e2)))
+    }
+
+    fn build_fields(&mut self, fields: &[&Tree]) -> Option<Vec<(Token, Ast)>> {
+        fields
+            .iter()
+            .map(|field| match trees(field)[..] {
+                [label, value] => Some((name_of(label)?, self.build(value))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Lower a statement in a block into a `Let` scoping over the rest of the block.
+    /// Expression statements are bound to `_`.
+    fn build_statement(&mut self, statement: &Tree, rest: Ast) -> Ast {
+        match statement.children.first() {
+            Some(Child::Token(t)) if t.kind == TokenKind::KeywordLet => {
+                let [binding, value] = trees(statement)[..] else {
+                    return Ast::Err;
+                };
+                let Some(pattern) = self.build_pattern(binding) else {
+                    return Ast::Err;
+                };
+
+                Ast::Let(pattern, Box::new(self.build(value)), Box::new(rest))
+            }
+            Some(Child::Tree(value)) => {
+                let Some(Child::Token(semi)) = statement.children.last() else {
+                    return Ast::Err;
+                };
+                let discard = Token {
+                    kind: TokenKind::Name("_".to_string()),
+                    ..semi.clone()
+                };
+
+                Ast::Let(
+                    Pattern::Wildcard(discard),
+                    Box::new(self.build(value)),
+                    Box::new(rest),
+                )
+            }
+            _ => Ast::Err,
         }
     }
 }
 
+fn is_type(kind: TreeKind) -> bool {
+    matches!(
+        kind,
+        TypeExpr | FunctionType | TupleType | RecordType | Unit
+    )
+}
+
+/// All the subtrees of a tree, leaving out its tokens.
+fn trees(tree: &Tree) -> Vec<&Tree> {
+    tree.children
+        .iter()
+        .filter_map(|c| match c {
+            Child::Tree(t) => Some(t),
+            Child::Token(_) => None,
+        })
+        .collect()
+}
+
+/// Find the token of the first `Name` in a tree, ie. the name of a parameter or binding.
+fn name_of(tree: &Tree) -> Option<Token> {
+    if tree.kind == Name {
+        return match tree.children.first() {
+            Some(Child::Token(token)) => Some(token.clone()),
+            _ => None,
+        };
+    }
+
+    trees(tree)
+        .into_iter()
+        .find(|t| t.kind == Name)
+        .and_then(name_of)
+}
+
+/// The position from the first to the last token of a tree.
+fn span(tree: &Tree) -> Position {
+    let start = first_position(tree);
+    last_position(tree).map_or(start, |end| start.to(end))
+}
+
+fn last_position(tree: &Tree) -> Option<Position> {
+    tree.children.iter().rev().find_map(|c| match c {
+        Child::Token(t) => Some(t.position),
+        Child::Tree(t) => last_position(t),
+    })
+}
+
+/// The position of the first token in a tree, falling back to the start of the source.
+fn first_position(tree: &Tree) -> Position {
+    fn find(tree: &Tree) -> Option<Position> {
+        tree.children.iter().find_map(|c| match c {
+            Child::Token(t) => Some(t.position),
+            Child::Tree(t) => find(t),
+        })
+    }
+
+    find(tree).unwrap_or(Position {
+        line: 0,
+        column: 0,
+        begin: 0,
+        end: 0,
+    })
+}
+
 pub type Input = crate::phase::parser::Output;
-pub type Output = HashMap<PathBuf, Ast>;
+pub type Output = HashMap<PathBuf, Program>;
 impl Phase<Input, Output> for AstBuilder {
     fn new() -> Self {
-        AstBuilder
+        AstBuilder::new(&PathBuf::new())
     }
 
-    fn run(self: &mut Self, _config: &crate::config::Config, input: &Input) -> PhaseResult<Output> {
+    fn run(&mut self, _config: &crate::config::Config, input: &Input) -> PhaseResult<Output> {
         let mut out = HashMap::new();
+        let mut errs = Vec::new();
 
         for (source_path, cst) in input {
-            let ast = build(cst);
-            out.insert(source_path.clone(), ast);
+            *self = AstBuilder::new(source_path);
+            let program = self.build_file(cst);
+            errs.append(&mut self.errors);
+            out.insert(source_path.clone(), program);
+        }
+
+        if !errs.is_empty() {
+            PhaseResult::Err(errs)
+        } else {
+            PhaseResult::Ok(out)
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{language::token::Position, phase::testing};
+
+    /// The printed definition of `name` in a source, as lines.
+    fn definition(source: &str, name: &str) -> Vec<String> {
+        let program = testing::program(source);
+        let printed = program.get(name).unwrap().pretty_print();
+        printed.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn lambdas_are_nested_abstractions() {
+        assert_eq!(
+            definition("def main() { \\a b -> a }", "main"),
+            [
+                "Definition 'main'",
+                "  Params",
+                "  Abstraction",
+                "    'a'",
+                "    Abstraction",
+                "      'b'",
+                "      Name 'a'",
+            ]
+        );
+    }
+
+    #[test]
+    fn statements_are_nested_lets() {
+        assert_eq!(
+            definition("def main() { let x = 1; x; x }", "main"),
+            [
+                "Definition 'main'",
+                "  Params",
+                "  Let",
+                "    'x'",
+                "    Literal '1'",
+                "    Let",
+                "      '_'",
+                "      Name 'x'",
+                "      Name 'x'",
+            ]
+        );
+    }
+
+    #[test]
+    fn calls_without_arguments_pass_unit() {
+        assert_eq!(
+            definition("def main() { f() }", "main"),
+            [
+                "Definition 'main'",
+                "  Params",
+                "  Application",
+                "    Name 'f'",
+                "    Unit",
+            ]
+        );
+    }
+
+    #[test]
+    fn definitions_span_from_def_to_the_end_of_their_body() {
+        let source = "def f(x: int): int {\n  x\n}\ndef main() { f(1) }";
+        let program = testing::program(source);
+        let f = program.get("f").unwrap();
+        assert_eq!(
+            definition(source, "f")[..2],
+            ["Definition 'f'", "  Params 'x: int': 'int'"]
+        );
+        assert_eq!(
+            f.position,
+            Position {
+                line: 0,
+                column: 0,
+                begin: 0,
+                end: source.find("\ndef main").unwrap(),
+            }
+        );
+        assert_eq!(f.name.position.begin, 4);
+    }
 
-        PhaseResult::Ok(out)
+    #[test]
+    fn type_definitions_keep_their_constructors_in_order() {
+        let program =
+            testing::program("type Result(e, a) = Err(e) | Ok(a) | Pending\ndef main() { 1 }");
+        assert_eq!(
+            program.types[0]
+                .pretty_print()
+                .lines()
+                .collect::<Vec<&str>>(),
+            [
+                "Type 'Result'",
+                "  Params 'e' 'a'",
+                "  Constructor 'Err' 'e'",
+                "  Constructor 'Ok' 'a'",
+                "  Constructor 'Pending'",
+            ]
+        );
     }
 }
@@ -1,99 +1,643 @@
-use std::{collections::HashMap, fmt::Display, path::PathBuf, str::FromStr};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 use crate::{
+    builtins::Builtins,
     language::{
-        ast::Ast,
-        token::{Token, TokenKind},
+        ast::{Ast, Definition, Pattern},
+        token::{Position, Token, TokenKind},
     },
-    message::Message,
+    message::{Content, Message, Severity},
     phase::{Phase, PhaseResult},
+    prelude,
 };
 
 pub struct Interpreter {
+    source_path: PathBuf,
     errors: Vec<Message>,
+    /// The top level definitions of the program, by name
+    definitions: HashMap<String, Value>,
+    /// The definitions of the prelude, which the program's own definitions take the place of
+    prelude: HashMap<String, Value>,
+    /// The constructors of the data types of the program, and how many fields each takes
+    constructors: HashMap<String, usize>,
+    builtins: Builtins,
+    /// How many evaluations are nested in each other right now
+    depth: usize,
 }
 
+/// How deeply evaluations can nest before the program is stopped, rather than the interpreter
+/// running out of stack. Calls in tail position don't nest, so loops written as tail recursive
+/// functions can go on for as long as they need.
+const MAX_DEPTH: usize = 10_000;
+
+/// The stack the interpreter has to be run with, so it can nest evaluations `MAX_DEPTH` deep.
+pub const STACK_SIZE: usize = 1 << 30;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     Bool(bool),
-    Number(f64),
-    Func(Token, Ast),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Unit,
+    Closure(Rc<Closure>),
+    Native(Rc<Native>),
+    Data(Rc<Data>),
+    Tuple(Vec<Value>),
+    List(List),
+    /// The fields of a record by their labels
+    Record(BTreeMap<String, Value>),
+}
+
+impl Value {
+    fn is_function(&self) -> bool {
+        match self {
+            Value::Closure(_) | Value::Native(_) => true,
+            Value::Data(data) => data.fields.len() < data.arity,
+            _ => false,
+        }
+    }
+
+    /// Whether two values are the same, which can't be told for functions.
+    pub fn equals(&self, other: &Value) -> Result<bool, String> {
+        match (self, other) {
+            (v1, v2) if v1.is_function() || v2.is_function() => {
+                Err("functions cannot be compared".to_string())
+            }
+            (Value::Data(d1), Value::Data(d2)) => {
+                if d1.constructor != d2.constructor {
+                    return Ok(false);
+                }
+                all_equal(&d1.fields, &d2.fields)
+            }
+            (Value::Tuple(e1), Value::Tuple(e2)) => all_equal(e1, e2),
+            (Value::List(l1), Value::List(l2)) => {
+                let vs1 = l1.iter().cloned().collect::<Vec<Value>>();
+                let vs2 = l2.iter().cloned().collect::<Vec<Value>>();
+                Ok(vs1.len() == vs2.len() && all_equal(&vs1, &vs2)?)
+            }
+            (Value::Record(f1), Value::Record(f2)) => {
+                let vs1 = f1.values().cloned().collect::<Vec<Value>>();
+                let vs2 = f2.values().cloned().collect::<Vec<Value>>();
+                all_equal(&vs1, &vs2)
+            }
+            (v1, v2) => Ok(v1 == v2),
+        }
+    }
+}
+
+fn all_equal(vs1: &[Value], vs2: &[Value]) -> Result<bool, String> {
+    for (v1, v2) in vs1.iter().zip(vs2) {
+        if !v1.equals(v2)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// A function value together with the environment it was created in.
+#[derive(Debug, PartialEq)]
+pub struct Closure {
+    param: Pattern,
+    body: Ast,
+    environment: Environment,
+}
+
+/// What is left to do of an expression, once everything but a call in tail position is done.
+enum Tail {
+    Value(Value),
+    /// The body of the closure still has to be evaluated, in the environment it is called with
+    Call(Rc<Closure>, Environment),
+}
+
+/// A top level definition as a closure, made once rather than every time it is used.
+/// Every definition takes at least `()`, so it always is a function.
+fn global(definition: &Definition) -> Value {
+    let Ast::Abstraction(param, _, body) = definition.value() else {
+        unreachable!("definitions are functions")
+    };
+    Value::Closure(Rc::new(Closure {
+        param,
+        body: *body,
+        environment: Environment::new(),
+    }))
+}
+
+/// A value of a data type, tagged with the constructor that made it.
+/// A constructor that hasn't been given all of its fields yet is a function.
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    constructor: String,
+    arity: usize,
+    fields: Vec<Value>,
+}
+
+/// A built-in function, along with the arguments it has been given so far.
+/// It is called once it has all of them.
+#[derive(Debug, PartialEq)]
+pub struct Native {
+    name: String,
+    arity: usize,
+    arguments: Vec<Value>,
+}
+
+/// An immutable list of values, as a linked list of cells that lists made from it share.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct List(Option<Rc<Cell>>);
+
+#[derive(Debug, PartialEq)]
+struct Cell {
+    head: Value,
+    tail: List,
+}
+
+impl List {
+    /// The list with `head` in front of the values of this one.
+    pub fn cons(&self, head: Value) -> Self {
+        Self(Some(Rc::new(Cell {
+            head,
+            tail: self.clone(),
+        })))
+    }
+
+    /// The first value of the list and the list of the values after it, unless it is empty.
+    pub fn split_first(&self) -> Option<(&Value, &List)> {
+        self.0.as_ref().map(|cell| (&cell.head, &cell.tail))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        let mut list = self;
+        std::iter::from_fn(move || {
+            let cell = list.0.as_ref()?;
+            list = &cell.tail;
+            Some(&cell.head)
+        })
+    }
+}
+
+impl Drop for List {
+    /// The cells no other list shares are dropped one after the other, rather than each
+    /// dropping the next, which would nest as deep as the list is long.
+    fn drop(&mut self) {
+        let mut next = self.0.take();
+        while let Some(cell) = next {
+            next = match Rc::try_unwrap(cell) {
+                Ok(mut cell) => cell.tail.0.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+impl FromIterator<Value> for List {
+    fn from_iter<T: IntoIterator<Item = Value>>(values: T) -> Self {
+        let values = values.into_iter().collect::<Vec<Value>>();
+        values
+            .into_iter()
+            .rev()
+            .fold(List::default(), |list, v| list.cons(v))
+    }
+}
+
+/// The local bindings in scope at some point of a program, as a linked list of frames.
+/// Frames are shared and never change, so capturing an environment in a closure is cheap,
+/// and bindings made later can't affect what the closure sees.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Environment(Option<Rc<Frame>>);
+
+#[derive(Debug, PartialEq)]
+struct Frame {
+    name: String,
+    value: Value,
+    parent: Environment,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self(None)
+    }
+
+    /// A new environment where `name` is bound to `value`, shadowing any outer binding.
+    pub fn bind(&self, name: String, value: Value) -> Self {
+        Self(Some(Rc::new(Frame {
+            name,
+            value,
+            parent: self.clone(),
+        })))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        let mut environment = self;
+        while let Some(frame) = &environment.0 {
+            if frame.name == name {
+                return Some(&frame.value);
+            }
+            environment = &frame.parent;
+        }
+        None
+    }
 }
 
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Bool(v) => write!(f, "{}", v),
-            Value::Number(v) => write!(f, "{}", v),
-            Value::Func(_, _) => write!(f, "func",), // TODO: Can we do better here?
+            Value::Int(v) => write!(f, "{}", v),
+            // Floats always show a fractional part, so they can be told apart from ints
+            Value::Float(v) => write!(f, "{:?}", v),
+            Value::String(v) => write!(f, "{}", v),
+            Value::Unit => write!(f, "()"),
+            Value::Closure(_) | Value::Native(_) => write!(f, "func",), // TODO: Can we do better here?
+            Value::Data(data) if data.fields.len() < data.arity => write!(f, "func"),
+            Value::Data(data) if data.fields.is_empty() => write!(f, "{}", data.constructor),
+            Value::Data(data) => write!(f, "{}({})", data.constructor, nested(&data.fields)),
+            Value::Tuple(elements) => write!(f, "({})", nested(elements)),
+            Value::List(list) => {
+                write!(
+                    f,
+                    "[{}]",
+                    nested(&list.iter().cloned().collect::<Vec<Value>>())
+                )
+            }
+            Value::Record(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(label, v)| format!("{}: {}", label, nested(std::slice::from_ref(v))))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "{{ {} }}", fields)
+            }
         }
     }
 }
 
+/// The value a literal stands for.
+fn literal(t: &Token) -> Option<Value> {
+    match t.kind {
+        TokenKind::LiteralInt(v) => Some(Value::Int(v)),
+        TokenKind::LiteralFloat(v) => Some(Value::Float(v)),
+        TokenKind::LiteralBool(v) => Some(Value::Bool(v)),
+        TokenKind::LiteralString(ref v) => Some(Value::String(v.clone())),
+        _ => None,
+    }
+}
+
+/// The environment with the names of a pattern bound to the parts of the value they match,
+/// or nothing if the value doesn't match the pattern.
+fn matches(pattern: &Pattern, value: &Value, environment: &Environment) -> Option<Environment> {
+    let all = |patterns: &[Pattern], values: &[Value]| {
+        let mut environment = environment.clone();
+        for (pattern, value) in patterns.iter().zip(values) {
+            environment = matches(pattern, value, &environment)?;
+        }
+        Some(environment)
+    };
+
+    match (pattern, value) {
+        (Pattern::Name(t), value) => Some(environment.bind(t.text(), value.clone())),
+        (Pattern::Wildcard(_), _) => Some(environment.clone()),
+        (Pattern::Literal(t), value) => {
+            (literal(t).as_ref() == Some(value)).then(|| environment.clone())
+        }
+        (Pattern::Tuple(patterns, _), Value::Tuple(values)) if patterns.len() == values.len() => {
+            all(patterns, values)
+        }
+        (Pattern::Constructor(t, patterns, _), Value::Data(data))
+            if data.constructor == t.text() && patterns.len() == data.fields.len() =>
+        {
+            all(patterns, &data.fields)
+        }
+        _ => None,
+    }
+}
+
+/// Values shown inside another value, strings are quoted so they can be told apart.
+fn nested(values: &[Value]) -> String {
+    values
+        .iter()
+        .map(|v| match v {
+            Value::String(s) => format!("{:?}", s),
+            v => v.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
 impl Interpreter {
     fn new() -> Self {
-        Self { errors: Vec::new() }
+        Self {
+            source_path: PathBuf::new(),
+            errors: Vec::new(),
+            definitions: HashMap::new(),
+            prelude: HashMap::new(),
+            constructors: HashMap::new(),
+            builtins: Builtins::default(),
+            depth: 0,
+        }
     }
 
-    fn interpret(
+    /// An interpreter that can call the functions of `builtins`, rather than the default ones.
+    pub fn with_builtins(builtins: Builtins) -> Self {
+        Self {
+            builtins,
+            ..Interpreter::new()
+        }
+    }
+
+    fn error<T>(&mut self, position: Position, message: String) -> Result<T, ()> {
+        self.errors.push(Message {
+            severity: Severity::Error,
+            position,
+            content: Content {
+                message,
+                indicator_message: Some(" here".to_string()),
+                fix_hint: None,
+            },
+            source_path: self.source_path.clone(),
+        });
+        Err(())
+    }
+
+    /// Bind the names of a pattern that has to match, like the one of a `let`.
+    fn bind(
         &mut self,
-        ast: &Ast,
-        environment: &mut HashMap<String, Value>,
-    ) -> Result<Value, ()> {
-        match ast {
-            Ast::Err => Err(()),
-            Ast::Expr(e) => self.interpret(e, environment),
-            Ast::Literal(t) => match t.kind {
-                TokenKind::LiteralNumber(v) => Ok(Value::Number(v)),
-                TokenKind::LiteralBool(v) => Ok(Value::Bool(v)),
-                _ => panic!("SFL ERROR: unhandled literal '{:?}'", t),
+        pattern: &Pattern,
+        value: Value,
+        environment: &Environment,
+    ) -> Result<Environment, ()> {
+        match matches(pattern, &value, environment) {
+            Some(environment) => Ok(environment),
+            None => self.error(
+                pattern.position(),
+                format!("{} does not match {}", value, pattern),
+            ),
+        }
+    }
+
+    /// Build, update or take a field out of a record.
+    fn record(&mut self, ast: &Ast, environment: &Environment) -> Result<Value, ()> {
+        let (mut record, fields) = match ast {
+            Ast::Record(fields) => (BTreeMap::new(), fields),
+            Ast::RecordUpdate(e, fields) => match self.interpret(e, environment)? {
+                Value::Record(record) => (record, fields),
+                v => return self.error(e.position(), format!("{} is not a record", v)),
             },
-            Ast::Name(t) => {
-                let Some(v) = environment.get(&t.text()) else {
-                    panic!("TODO: add unknown name error")
+            Ast::Field(e, label) => {
+                return match self.interpret(e, environment)? {
+                    Value::Record(record) if record.contains_key(&label.text()) => {
+                        Ok(record[&label.text()].clone())
+                    }
+                    v => self.error(
+                        label.position,
+                        format!("{} has no field '{}'", v, label.text()),
+                    ),
                 };
-                Ok((*v).clone())
             }
-            Ast::Let(t, e1, e2) => {
+            _ => return Err(()),
+        };
+
+        for (label, e) in fields {
+            let v = self.interpret(e, environment)?;
+            record.insert(label.text(), v);
+        }
+        Ok(Value::Record(record))
+    }
+
+    fn interpret(&mut self, ast: &Ast, environment: &Environment) -> Result<Value, ()> {
+        if self.depth == MAX_DEPTH {
+            return self.error(
+                ast.position(),
+                "the program recursed too deeply".to_string(),
+            );
+        }
+
+        self.depth += 1;
+        let mut tail = self.step(ast, environment);
+        while let Ok(Tail::Call(closure, environment)) = tail {
+            tail = self.step(&closure.body, &environment);
+        }
+        self.depth -= 1;
+
+        match tail? {
+            Tail::Value(v) => Ok(v),
+            Tail::Call(_, _) => unreachable!("calls are made until there is a value"),
+        }
+    }
+
+    /// Evaluate an expression up to a call in tail position, which is left to `interpret`
+    /// so it doesn't nest.
+    fn step(&mut self, ast: &Ast, environment: &Environment) -> Result<Tail, ()> {
+        match ast {
+            Ast::Expr(e) => self.step(e, environment),
+            Ast::Let(pattern, e1, e2) => {
                 let r1 = self.interpret(e1, environment)?;
-                environment.insert(t.text(), r1);
-                self.interpret(e2, environment)
+                let environment = self.bind(pattern, r1, environment)?;
+                self.step(e2, &environment)
             }
-            Ast::BinaryOp(t, e1, e2) => {
-                let r1 = self.interpret(e1, environment)?;
-                let r2 = self.interpret(e2, environment)?;
-                match t.kind {
-                    TokenKind::Plus => {
-                        let Value::Number(n1) = r1 else {
-                            panic!("SFL ERROR: Typechecker missed binary op '{:?}'", t);
-                        };
-                        let Value::Number(n2) = r2 else {
-                            panic!("SFL ERROR: Typechecker missed binary op '{:?}'", t);
-                        };
-                        Ok(Value::Number(n1 + n2))
-                    }
-                    TokenKind::Minus => {
-                        let Value::Number(n1) = r1 else {
-                            panic!("SFL ERROR: Typechecker missed binary op '{:?}'", t);
-                        };
-                        let Value::Number(n2) = r2 else {
-                            panic!("SFL ERROR: Typechecker missed binary op '{:?}'", t);
-                        };
-                        Ok(Value::Number(n1 - n2))
+            Ast::If(c, t, e) => match self.interpret(c, environment)? {
+                Value::Bool(true) => self.step(t, environment),
+                Value::Bool(false) => self.step(e, environment),
+                v => self.error(c.position(), format!("expected a bool but found {}", v)),
+            },
+            Ast::Application(e1, e2) => self.apply(e1, e2, environment),
+            Ast::Match(t, e, arms) => {
+                let value = self.interpret(e, environment)?;
+                for (pattern, body) in arms {
+                    if let Some(environment) = matches(pattern, &value, environment) {
+                        return self.step(body, &environment);
                     }
-                    _ => panic!("SFL ERROR: unhandled binary operator '{:?}'", t),
                 }
+                self.error(t.position, format!("no arm matches {}", value))
+            }
+            _ => Ok(Tail::Value(self.evaluate(ast, environment)?)),
+        }
+    }
+
+    /// The value of an expression that can't end in a call.
+    fn evaluate(&mut self, ast: &Ast, environment: &Environment) -> Result<Value, ()> {
+        match ast {
+            Ast::Err => Err(()),
+            Ast::Expr(_)
+            | Ast::Let(_, _, _)
+            | Ast::If(_, _, _)
+            | Ast::Application(_, _)
+            | Ast::Match(_, _, _) => self.interpret(ast, environment),
+            Ast::Literal(t) => match literal(t) {
+                Some(v) => Ok(v),
+                None => self.error(t.position, format!("unknown literal '{}'", t.text())),
+            },
+            Ast::Name(t) => self.lookup(t, environment),
+            Ast::Unit(_) => Ok(Value::Unit),
+            Ast::Tuple(elements) => {
+                let mut values = Vec::new();
+                for element in elements {
+                    values.push(self.interpret(element, environment)?);
+                }
+                Ok(Value::Tuple(values))
             }
-            Ast::Abstraction(t, e) => Ok(Value::Func(t.clone(), (**e).clone())),
-            Ast::Application(e1, e2) => match self.interpret(e1, environment)? {
-                Value::Func(t, ast) => {
-                    let v2 = self.interpret(e2, environment)?;
-                    environment.insert(t.text(), v2);
-                    self.interpret(&ast, environment)
+            Ast::List(_, elements) => {
+                let mut values = Vec::new();
+                for element in elements {
+                    values.push(self.interpret(element, environment)?);
                 }
-                _ => panic!("SFL ERROR: cannot apply {:?}", e1),
+                Ok(Value::List(values.into_iter().collect()))
+            }
+            Ast::Record(_) | Ast::RecordUpdate(_, _) | Ast::Field(_, _) => {
+                self.record(ast, environment)
+            }
+            Ast::BinaryOp(t, e1, e2) if !self.is_built_in(t, environment) => {
+                // Operators defined by the program are applied like any other function
+                let application = Ast::Application(
+                    Box::new(Ast::Application(Box::new(Ast::Name(t.clone())), e1.clone())),
+                    e2.clone(),
+                );
+                self.interpret(&application, environment)
+            }
+            Ast::BinaryOp(t, e1, e2) => self.operator(t, e1, e2, environment),
+            Ast::UnaryOp(t, e) => match (&t.kind, self.interpret(e, environment)?) {
+                (TokenKind::Minus, Value::Int(n)) => match n.checked_neg() {
+                    Some(n) => Ok(Value::Int(n)),
+                    None => self.error(t.position, format!("-({}) does not fit in an int", n)),
+                },
+                (TokenKind::Minus, Value::Float(n)) => Ok(Value::Float(-n)),
+                (TokenKind::Bang, Value::Bool(b)) => Ok(Value::Bool(!b)),
+                (_, v) => self.error(t.position, format!("cannot apply '{}' to {}", t.text(), v)),
             },
+            Ast::Interpolation(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    text += &self.interpret(part, environment)?.to_string();
+                }
+                Ok(Value::String(text))
+            }
+            Ast::Abstraction(t, _, e) => Ok(Value::Closure(Rc::new(Closure {
+                param: t.clone(),
+                body: (**e).clone(),
+                environment: environment.clone(),
+            }))),
+        }
+    }
+
+    /// The value of a name, looking through the local bindings, the definitions of the program,
+    /// the prelude and then what is built in. The prelude itself can't see the program.
+    fn lookup(&mut self, t: &Token, environment: &Environment) -> Result<Value, ()> {
+        if let Some(v) = environment.get(&t.text()) {
+            return Ok(v.clone());
+        }
+        let definition = match self.definitions.get(&t.text()) {
+            Some(definition) if !prelude::is_prelude(&t.source_path) => Some(definition),
+            _ => self.prelude.get(&t.text()),
+        };
+        if let Some(v) = definition {
+            return Ok(v.clone());
         }
+        if let Some(&arity) = self.constructors.get(&t.text()) {
+            return Ok(Value::Data(Rc::new(Data {
+                constructor: t.text(),
+                arity,
+                fields: Vec::new(),
+            })));
+        }
+        if let Some(builtin) = self.builtins.get(&t.text()) {
+            if builtin.arity == 0 {
+                return self.call(&t.text(), &[], t.position);
+            }
+            return Ok(Value::Native(Rc::new(Native {
+                name: t.text(),
+                arity: builtin.arity,
+                arguments: Vec::new(),
+            })));
+        }
+        self.error(t.position, format!("'{}' is not defined", t.text()))
+    }
+
+    fn operator(
+        &mut self,
+        t: &Token,
+        e1: &Ast,
+        e2: &Ast,
+        environment: &Environment,
+    ) -> Result<Value, ()> {
+        let r1 = self.interpret(e1, environment)?;
+
+        // && and || only evaluate their right side when they have to
+        match (&t.kind, &r1) {
+            (TokenKind::AmpAmp, Value::Bool(false)) => return Ok(Value::Bool(false)),
+            (TokenKind::PipePipe, Value::Bool(true)) => return Ok(Value::Bool(true)),
+            (TokenKind::AmpAmp | TokenKind::PipePipe, Value::Bool(_)) => {
+                return self.interpret(e2, environment)
+            }
+            _ => {}
+        }
+
+        let r2 = self.interpret(e2, environment)?;
+        self.call(&t.text(), &[r1, r2], t.position)
+    }
+
+    fn apply(&mut self, e1: &Ast, e2: &Ast, environment: &Environment) -> Result<Tail, ()> {
+        let value = match self.interpret(e1, environment)? {
+            Value::Closure(closure) => {
+                let v2 = self.interpret(e2, environment)?;
+                let environment = self.bind(&closure.param, v2, &closure.environment)?;
+                return Ok(Tail::Call(closure, environment));
+            }
+            Value::Native(native) => {
+                let mut arguments = native.arguments.clone();
+                arguments.push(self.interpret(e2, environment)?);
+                let native = Native {
+                    name: native.name.clone(),
+                    arity: native.arity,
+                    arguments,
+                };
+
+                if native.arguments.len() < native.arity {
+                    return Ok(Tail::Value(Value::Native(Rc::new(native))));
+                }
+                self.call(&native.name, &native.arguments, e1.position())?
+            }
+            Value::Data(data) if data.fields.len() < data.arity => {
+                let mut fields = data.fields.clone();
+                fields.push(self.interpret(e2, environment)?);
+                Value::Data(Rc::new(Data {
+                    constructor: data.constructor.clone(),
+                    arity: data.arity,
+                    fields,
+                }))
+            }
+            v => return self.error(e1.position(), format!("cannot call {}", v)),
+        };
+        Ok(Tail::Value(value))
+    }
+
+    /// Call a built-in function. Its errors are reported at `position`, unless they say where.
+    fn call(&mut self, name: &str, arguments: &[Value], position: Position) -> Result<Value, ()> {
+        let Some(builtin) = self.builtins.get(name) else {
+            return self.error(position, format!("'{}' is not defined", name));
+        };
+        let message = match (builtin.function)(arguments) {
+            Ok(v) => return Ok(v),
+            Err(message) if message.source_path.as_os_str().is_empty() => Message {
+                position,
+                source_path: self.source_path.clone(),
+                ..*message
+            },
+            Err(message) => *message,
+        };
+        self.errors.push(message);
+        Err(())
+    }
+
+    /// Whether a name refers to something built in, rather than something the program binds.
+    fn is_built_in(&self, name: &Token, environment: &Environment) -> bool {
+        let name = name.text();
+        environment.get(&name).is_none()
+            && !self.definitions.contains_key(&name)
+            && !self.prelude.contains_key(&name)
     }
 }
 
@@ -104,16 +648,523 @@ impl Phase<Input, Output> for Interpreter {
         Interpreter::new()
     }
 
-    fn run(self: &mut Self, _config: &crate::config::Config, input: &Input) -> PhaseResult<Output> {
-        let entry_point = input
-            .get(&PathBuf::from_str("./main.sfl").unwrap())
-            .unwrap();
+    fn run(&mut self, config: &crate::config::Config, input: &Input) -> PhaseResult<Output> {
+        let source_path = config.entry_point.clone();
+        self.source_path = source_path.clone();
+        let Some(program) = input.get(&source_path) else {
+            return PhaseResult::Err(vec![Message {
+                severity: Severity::Error,
+                position: Position {
+                    line: 0,
+                    column: 0,
+                    begin: 0,
+                    end: 0,
+                },
+                content: Content {
+                    message: format!("there is no '{}' to run", source_path.display()),
+                    indicator_message: None,
+                    fix_hint: None,
+                },
+                source_path,
+            }]);
+        };
+
+        self.definitions = program
+            .definitions
+            .iter()
+            .map(|d| (d.name.text(), global(d)))
+            .collect();
+        self.prelude = input
+            .get(Path::new(prelude::PATH))
+            .iter()
+            .flat_map(|prelude| &prelude.definitions)
+            .map(|d| (d.name.text(), global(d)))
+            .collect();
+        self.constructors = program
+            .types
+            .iter()
+            .flat_map(|t| &t.constructors)
+            .map(|c| (c.name.text(), c.fields.len()))
+            .collect();
+
+        let Some(main) = program.get("main") else {
+            return PhaseResult::Err(vec![Message {
+                severity: Severity::Error,
+                position: Position {
+                    line: 0,
+                    column: 0,
+                    begin: 0,
+                    end: 0,
+                },
+                content: Content {
+                    message: "no 'main' function in program".to_string(),
+                    indicator_message: None,
+                    fix_hint: Some("define one using `def main() { ... }`".to_string()),
+                },
+                source_path,
+            }]);
+        };
+
+        if let Some(param) = main.params.first() {
+            return PhaseResult::Err(vec![Message {
+                severity: Severity::Error,
+                position: param.pattern.position(),
+                content: Content {
+                    message: "'main' cannot take parameters".to_string(),
+                    indicator_message: Some("remove this".to_string()),
+                    fix_hint: None,
+                },
+                source_path,
+            }]);
+        }
+
+        match self.interpret(&main.body, &Environment::new()) {
+            Ok(value) => PhaseResult::Ok(value),
+            Err(_) => PhaseResult::Err(self.errors.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        collections::{HashMap, VecDeque},
+        io::ErrorKind,
+        path::{Path, PathBuf},
+        rc::Rc,
+    };
+
+    use super::{Value, STACK_SIZE};
+    use crate::{
+        builtins::{self, Builtins},
+        config::Config,
+        io::Io,
+        language::types::{TType, TypeFunc},
+        message::{Message, Severity},
+        phase::{
+            interpreter::Interpreter,
+            testing::{build, check, PATH},
+            type_checker::TypeChecker,
+            Phase, PhaseResult,
+        },
+    };
+
+    fn interpret(source: &str) -> PhaseResult<Value> {
+        Interpreter::new().run(&Config::default(), &build(source))
+    }
+
+    fn run(source: &str) -> Value {
+        match interpret(source) {
+            PhaseResult::Ok(v) => v,
+            _ => panic!("program failed"),
+        }
+    }
+
+    fn run_err(source: &str) -> Vec<Message> {
+        match interpret(source) {
+            PhaseResult::Err(errors) => errors,
+            _ => panic!("program did not fail"),
+        }
+    }
+
+    #[test]
+    fn closures_ignore_later_shadowing() {
+        let source = "def main() { let x = 1; let f = \\y -> x; let x = 2; f(0) }";
+        assert_eq!(run(source), Value::Int(1));
+    }
+
+    #[test]
+    fn arguments_do_not_leak_into_the_caller() {
+        let source = "
+            def main() {
+                let x = 1;
+                let f = \\x -> x + 1;
+                let y = f(5);
+                x + y
+            }";
+        assert_eq!(run(source), Value::Int(7));
+    }
+
+    #[test]
+    fn returned_functions_keep_their_environment() {
+        let source = "
+            def adder(n: int) { \\x -> x + n }
+            def main() {
+                let add = adder(1);
+                let n = 5;
+                add(2)
+            }";
+        assert_eq!(run(source), Value::Int(3));
+    }
+
+    #[test]
+    fn curried_functions_capture_each_argument() {
+        let source = "
+            def main() {
+                let fst = \\x -> \\y -> x
+                    in let snd = \\x -> \\y -> y
+                    in fst(snd(1 + 2)(4))(5)
+            }";
+        assert_eq!(run(source), Value::Int(4));
+    }
+
+    #[test]
+    fn panics_are_reported_at_the_call() {
+        let errors = run_err("def main() { let f = \\x -> panic(x); f(1) + 2 }");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].content.message, "panicked with 1");
+        assert_eq!(errors[0].position.column, 27);
+    }
+
+    #[test]
+    fn the_program_run_is_the_entry_point_of_the_config() {
+        let config = Config {
+            entry_point: PathBuf::from("app.sfl"),
+            ..Config::default()
+        };
+        let mut program = build("def main() { 1 }");
+        let PhaseResult::Err(errors) = Interpreter::new().run(&config, &program) else {
+            panic!("program did not fail");
+        };
+        assert_eq!(errors[0].content.message, "there is no 'app.sfl' to run");
+
+        let main = program.remove(Path::new(PATH)).unwrap();
+        program.insert(PathBuf::from("app.sfl"), main);
+        let PhaseResult::Ok(value) = Interpreter::new().run(&config, &program) else {
+            panic!("program failed");
+        };
+        assert_eq!(value, Value::Int(1));
+    }
+
+    #[test]
+    fn panic_can_be_passed_around_like_any_function() {
+        let errors = run_err("def main() { let p = panic; p(\"x\") }");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].content.message, "panicked with x");
+        assert_eq!(errors[0].position.column, 28);
+    }
+
+    #[test]
+    fn registered_builtins_are_checked_and_called() {
+        let int = || TType::Application(TypeFunc::Int);
+        let mut builtins = Builtins::default();
+        builtins.register(
+            "halve",
+            TType::function(int(), int()),
+            |arguments| match arguments {
+                [Value::Int(n)] if n % 2 == 0 => Ok(Value::Int(n / 2)),
+                _ => Err(builtins::error(format!("cannot halve {}", arguments[0]))),
+            },
+        );
 
-        let result = self.interpret(entry_point, &mut HashMap::new());
-        if self.errors.len() == 0 {
-            PhaseResult::Ok(result.unwrap())
-        } else {
-            PhaseResult::Err(self.errors.clone())
+        let config = Config::default();
+        let program = build("def main() { halve(halve(12)) + halve(3) }");
+        let PhaseResult::Ok(_) =
+            TypeChecker::with_builtins(builtins.clone()).run(&config, &program)
+        else {
+            panic!("program was rejected");
+        };
+        let PhaseResult::Err(errors) = Interpreter::with_builtins(builtins).run(&config, &program)
+        else {
+            panic!("program did not fail");
+        };
+        assert_eq!(errors[0].content.message, "cannot halve 3");
+        assert_eq!(errors[0].position.column, 32);
+
+        let messages = check("def main() { halve(1) }");
+        assert_eq!(messages[0].content.message, "'halve' is not defined here");
+    }
+
+    #[test]
+    fn messages_of_builtins_that_say_where_are_kept() {
+        let mut builtins = Builtins::default();
+        builtins.register(
+            "deprecated",
+            TType::function(
+                TType::Application(TypeFunc::Unit),
+                TType::Variable("a".to_string()),
+            ),
+            |_| {
+                Err(Box::new(Message {
+                    severity: Severity::Warning,
+                    source_path: PathBuf::from("lib.sfl"),
+                    ..*builtins::error("'deprecated' is gone")
+                }))
+            },
+        );
+
+        let program = build("def main() { deprecated() }");
+        let PhaseResult::Err(errors) =
+            Interpreter::with_builtins(builtins).run(&Config::default(), &program)
+        else {
+            panic!("program did not fail");
+        };
+        assert_eq!(errors[0].severity, Severity::Warning);
+        assert_eq!(errors[0].source_path, PathBuf::from("lib.sfl"));
+        assert_eq!(errors[0].position.column, 0);
+    }
+
+    /// Input that is already there, output that is kept, and files that only exist in memory.
+    #[derive(Default)]
+    struct Memory {
+        input: VecDeque<String>,
+        output: String,
+        files: HashMap<PathBuf, String>,
+    }
+
+    impl Io for Memory {
+        fn print(&mut self, text: &str) {
+            self.output += text;
+        }
+
+        fn read_line(&mut self) -> Option<String> {
+            self.input.pop_front()
+        }
+
+        fn read_file(&mut self, path: &Path) -> std::io::Result<String> {
+            self.files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| std::io::Error::new(ErrorKind::NotFound, "no such file"))
+        }
+
+        fn write_file(&mut self, path: &Path, contents: &str) -> std::io::Result<()> {
+            self.files.insert(path.to_path_buf(), contents.to_string());
+            Ok(())
         }
     }
+
+    #[test]
+    fn io_goes_through_the_given_console_and_filesystem() {
+        let memory = Rc::new(RefCell::new(Memory {
+            input: VecDeque::from(["Ada".to_string()]),
+            ..Memory::default()
+        }));
+        let builtins = Builtins::with_io(memory.clone());
+        let interpret = |source: &str| {
+            Interpreter::with_builtins(builtins.clone()).run(&Config::default(), &build(source))
+        };
+
+        let source = r#"
+            def main() {
+                print("name? ");
+                let name = read_line();
+                write_file("names.txt", name);
+                println("hello " ++ read_file("names.txt"));
+                println([1, 2])
+            }"#;
+        let PhaseResult::Ok(value) = interpret(source) else {
+            panic!("program failed");
+        };
+        assert_eq!(value, Value::Unit);
+        assert_eq!(memory.borrow().output, "name? hello Ada\n[1, 2]\n");
+        assert_eq!(memory.borrow().files[Path::new("names.txt")], "Ada");
+
+        let PhaseResult::Err(errors) = interpret("def main() { read_file(read_line()) }") else {
+            panic!("program did not fail");
+        };
+        assert_eq!(errors[0].content.message, "there is no more input to read");
+    }
+
+    #[test]
+    fn declared_operators_are_called_like_functions() {
+        let source = "
+            infixr 5 --
+            infixl 1 |>
+            def (--)(a: int, b: int) { a - b }
+            def (|>)(x: a, f: a -> b) { f(x) }
+            def main() { 8 -- 4 -- 3 |> \\x -> x - 1 - 1 }";
+        assert_eq!(run(source), Value::Int(5));
+    }
+
+    #[test]
+    fn built_in_operators_can_be_passed_as_functions() {
+        let source = "def main() { let sub = (-) in sub(7, 2) }";
+        assert_eq!(run(source), Value::Int(5));
+    }
+
+    #[test]
+    fn prefix_operators_are_evaluated() {
+        let source = "def main() { if !(1 > 2) && !false then -2 * 3 - -1 else 0 }";
+        assert_eq!(run(source), Value::Int(-5));
+    }
+
+    #[test]
+    fn strings_are_concatenated() {
+        let source = r#"def main() { "a\n" ++ "" ++ substring("bcd", 0, 2) }"#;
+        assert_eq!(run(source), Value::String("a\nbc".to_string()));
+    }
+
+    #[test]
+    fn interpolated_strings_splice_in_values() {
+        let source = r#"
+            def main() {
+                let name = "x";
+                "{name} = {1 + 2}, {if true then "{name}!" else ""} \{ok\}"
+            }"#;
+        assert_eq!(run(source), Value::String("x = 3, x! {ok}".to_string()));
+    }
+
+    #[test]
+    fn int_overflow_is_reported() {
+        let source = "
+            def pow(b: int, e: int): int { if e == 0 then 1 else b * pow(b, e - 1) }
+            def main() { pow(2, 9 * 7) }";
+        let errors = run_err(source);
+        assert_eq!(
+            errors[0].content.message,
+            "2 * 4611686018427387904 does not fit in an int"
+        );
+    }
+
+    #[test]
+    fn constructors_build_tagged_values() {
+        let source = "
+            type Shape = Circle(float) | Rect(float, float)
+            type Option(a) = None | Some(a)
+            def main() {
+                let square = Rect(2.0);
+                if Some(square(2.0)) != None then Some(square(3.0)) else None
+            }";
+        assert_eq!(run(source).to_string(), "Some(Rect(2.0, 3.0))");
+    }
+
+    #[test]
+    fn tuples_are_taken_apart_by_bindings_and_parameters() {
+        let source = r#"
+            def divmod(a: int, b: int) { (a / b, a % b) }
+            def swap((a, b): (int, string)) { (b, a) }
+            def main() {
+                let (q, r) = divmod(17, 5);
+                let ((x, y), z) = (swap((q, "r")), r);
+                (x, y + z, (q, r) == (3, 2))
+            }"#;
+        assert_eq!(run(source).to_string(), r#"("r", 5, true)"#);
+    }
+
+    #[test]
+    fn records_are_built_read_and_updated() {
+        let source = r#"
+            def main() {
+                let age = \r -> r.age;
+                let older = \r -> { r | age: r.age + 1 };
+                let p = { name: "x", age: 3 };
+                let d = { age: 7, legs: 4 };
+                (age(p) + age(older(d)), older(p), older(d).legs)
+            }"#;
+        assert_eq!(run(source).to_string(), r#"(11, { age: 4, name: "x" }, 4)"#);
+    }
+
+    #[test]
+    fn lists_are_built_with_literals_and_the_prelude() {
+        let source = r#"
+            def main() {
+                let squares = map(\x -> x * x, range(1, 6));
+                let evens = filter(\x -> x % 2 == 0, squares);
+                (evens, fold(\a b -> a + b, 0, squares), reverse(["a", "b"]), length([]))
+            }"#;
+        assert_eq!(run(source).to_string(), r#"([4, 16], 55, ["b", "a"], 0)"#);
+
+        let errors = run_err("def main() { head(tail([1])) }");
+        assert_eq!(
+            errors[0].content.message,
+            "cannot take the head of an empty list"
+        );
+    }
+
+    #[test]
+    fn programs_can_replace_what_the_prelude_defines() {
+        // The prelude's own `length` still uses the prelude's `fold`
+        let source = r#"
+            def fold(s: string): string { s ++ "!" }
+            def main() { (fold("a"), length([1, 2, 3])) }"#;
+        assert_eq!(run(source).to_string(), r#"("a!", 3)"#);
+    }
+
+    #[test]
+    fn match_picks_the_first_arm_that_fits() {
+        let source = r#"
+            type Option(a) = None | Some(a)
+            def describe(o: Option((int, bool))): string {
+                match o {
+                    None -> "none",
+                    Some((0, _)) -> "zero",
+                    Some((n, true)) -> "{n} on",
+                    Some((_, false)) -> "off",
+                }
+            }
+            def main() {
+                describe(Some((0, true))) ++ describe(Some((2, true))) ++ describe(None)
+            }"#;
+        assert_eq!(run(source), Value::String("zero2 onnone".to_string()));
+    }
+
+    #[test]
+    fn only_the_chosen_branch_of_an_if_is_evaluated() {
+        let source = "def main() { if 1 > 2 then panic(\"then\") else if true { 3 } else { panic(\"else\") } }";
+        assert_eq!(run(source), Value::Int(3));
+    }
+
+    #[test]
+    fn tail_calls_do_not_nest() {
+        let source = "
+            def count(n: int, total: int): int {
+                if n == 0 then total else count(n - 1, total + 1)
+            }
+            def main() { count(20000, 0) }";
+        assert_eq!(run(source), Value::Int(20000));
+    }
+
+    #[test]
+    fn recursing_too_deeply_is_reported() {
+        let source = "
+            def sum(n: int): int { if n == 0 then 0 else n + sum(n - 1) }
+            def main() { sum(100000) }";
+        let errors = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(|| run_err(source))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].content.message, "the program recursed too deeply");
+    }
+
+    #[test]
+    fn and_and_or_only_evaluate_their_right_side_when_needed() {
+        let source = "
+            def main() {
+                (false && panic(\"and\"), true || panic(\"or\"), true && 1 < 2, false || 7 / 2 == 3)
+            }";
+        assert_eq!(
+            run(source),
+            Value::Tuple(vec![
+                Value::Bool(false),
+                Value::Bool(true),
+                Value::Bool(true),
+                Value::Bool(true)
+            ])
+        );
+    }
+
+    #[test]
+    fn built_in_operators_are_functions() {
+        let source =
+            "def main() { let add = (+); (fold((+), 0, [1, 2, 3]), add(4)(5), (&&)(true, false)) }";
+        assert_eq!(
+            run(source),
+            Value::Tuple(vec![Value::Int(6), Value::Int(9), Value::Bool(false)])
+        );
+    }
+
+    #[test]
+    fn the_prelude_works_on_long_lists() {
+        let source = "
+            def main() {
+                let xs = map(\\x -> x * 2, filter(\\x -> x % 2 == 0, range(0, 20000)));
+                (length(xs), head(reverse(xs)), fold(\\a b -> a + b, 0, xs))
+            }";
+        assert_eq!(run(source).to_string(), "(10000, 39996, 199980000)");
+    }
 }
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fmt::Debug, path::PathBuf};
+use std::{collections::HashMap, fmt::Debug, ops::Range, path::PathBuf};
 
 use crate::{
     config::Config,
@@ -10,8 +10,14 @@ use crate::{
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
 pub enum LexerError {
     UnexpectedToken(char),
+    UnterminatedString,
+    InvalidEscape(char),
+    /// The lexer reports what is wrong with the number itself
+    InvalidNumber,
 }
 
+const OPERATOR_CHARACTERS: &str = "+-*/%=!<>&|^~?@$";
+
 pub struct Lexer {
     is_ok: bool,
     begin: usize,
@@ -22,6 +28,8 @@ pub struct Lexer {
     source_path: PathBuf,
     characters: Vec<char>,
     errors: Vec<Message>,
+    /// For every interpolation in a string that is being lexed, how many `{` deep it is
+    interpolations: Vec<usize>,
 }
 
 impl Lexer {
@@ -36,14 +44,15 @@ impl Lexer {
             source_path: source.0.clone(),
             characters: source.1.to_owned().chars().collect(),
             errors: Vec::new(),
+            interpolations: Vec::new(),
         }
     }
 
-    fn eof(self: &Self) -> bool {
+    fn eof(&self) -> bool {
         self.end >= self.characters.len()
     }
 
-    fn advance(self: &mut Self) -> Option<char> {
+    fn advance(&mut self) -> Option<char> {
         if self.eof() {
             return None;
         }
@@ -59,18 +68,18 @@ impl Lexer {
         Some(c)
     }
 
-    fn nth(self: &Self, lookahead: usize) -> char {
+    fn nth(&self, lookahead: usize) -> char {
         let index = (self.end + lookahead).min(self.characters.len() - 1);
         self.characters[index]
     }
 
-    fn whitespace(self: &mut Self) {
+    fn whitespace(&mut self) {
         while !self.eof() && self.nth(0).is_whitespace() {
             self.advance();
         }
     }
 
-    fn keyword(self: &mut Self, keyword: &str) -> bool {
+    fn keyword(&mut self, keyword: &str) -> bool {
         if self.end - 1 + keyword.len() > self.characters.len() {
             return false;
         }
@@ -83,6 +92,14 @@ impl Lexer {
             .zip(keyword.chars())
             .all(|(&a, b)| a == b);
 
+        // Keywords made of letters must not just be the start of a longer name like 'input'
+        let is_word = keyword.chars().all(char::is_alphabetic);
+        let followed_by_name = self
+            .characters
+            .get(self.end - 1 + keyword.len())
+            .is_some_and(|c| c.is_alphanumeric() || *c == '_');
+        let matches = matches && !(is_word && followed_by_name);
+
         if matches {
             for _ in 0..keyword.len() - 1 {
                 self.advance();
@@ -92,41 +109,260 @@ impl Lexer {
         matches
     }
 
-    fn lexeme(self: &mut Self) -> Option<String> {
+    fn lexeme(&mut self) -> Option<String> {
         self.characters
             .get(self.begin..self.end)
             .map(|s| s.iter().collect::<String>())
     }
 
-    fn number(self: &mut Self) -> TokenKind {
-        let p = self.nth(0);
-        let mut found_dot = false;
-        while !self.eof() && (p.is_digit(10) || p == '.') {
-            if p == '.' {
-                if found_dot {
-                    self.errors.push(Message {
-                        severity: Severity::Error,
-                        position: self.position(),
-                        source_path: self.source_path.clone(),
-                        content: Content {
-                            message: format!("this number has two decimal dots"),
-                            indicator_message: Some("second dot is here".to_string()),
-                            fix_hint: None,
-                        },
-                    });
+    /// Numbers are ints, unless they have a fractional part or an exponent like `1.5e-3`.
+    /// Ints can also be written in hex `0xFF`, binary `0b1010` or octal `0o17`,
+    /// and `_` can be used to separate the digits of any number, like `1_000_000`.
+    fn number(&mut self) -> TokenKind {
+        let radix = match (self.characters[self.begin], self.eof(), self.nth(0)) {
+            ('0', false, 'x') => 16,
+            ('0', false, 'o') => 8,
+            ('0', false, 'b') => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.advance();
+        }
+
+        // Everything that could be part of the number is consumed, so a malformed number is
+        // reported as a whole rather than being split into several tokens
+        while !self.eof() {
+            let c = self.nth(0);
+            let exponent_sign = radix == 10
+                && matches!(c, '+' | '-')
+                && matches!(self.characters[self.end - 1], 'e' | 'E');
+            if !(c.is_alphanumeric() || c == '_' || c == '.' || exponent_sign) {
+                break;
+            }
+            self.advance();
+        }
+
+        match self.number_value(radix) {
+            Ok(kind) => kind,
+            Err((range, message)) => {
+                self.is_ok = false;
+                self.errors.push(Message {
+                    severity: Severity::Error,
+                    position: Position {
+                        line: self.line,
+                        column: self.start_column + (range.start - self.begin),
+                        begin: range.start,
+                        end: range.end,
+                    },
+                    source_path: self.source_path.clone(),
+                    content: Content {
+                        message,
+                        indicator_message: Some("here".to_string()),
+                        fix_hint: None,
+                    },
+                });
+                TokenKind::Error(LexerError::InvalidNumber)
+            }
+        }
+    }
+
+    /// The value of the number that was just consumed, or where and how it is malformed.
+    fn number_value(&self, radix: u32) -> Result<TokenKind, (Range<usize>, String)> {
+        let literal = &self.characters[self.begin..self.end];
+        let at = |i: usize| self.begin + i..self.begin + i + 1;
+        let whole = self.begin..self.end;
+
+        if radix != 10 {
+            let name = match radix {
+                16 => "hex",
+                8 => "octal",
+                _ => "binary",
+            };
+            if let Some(i) =
+                (2..literal.len()).find(|&i| !literal[i].is_digit(radix) && literal[i] != '_')
+            {
+                return Err((at(i), format!("'{}' is not a {} digit", literal[i], name)));
+            }
+
+            let digits = literal[2..]
+                .iter()
+                .filter(|c| **c != '_')
+                .collect::<String>();
+            if digits.is_empty() {
+                return Err((
+                    whole,
+                    format!("expected {} digits after '0{}'", name, literal[1]),
+                ));
+            }
+            if let Some(i) = misplaced_separator(literal, radix) {
+                return Err((at(i), "a '_' has to be followed by a digit".to_string()));
+            }
+            return i64::from_str_radix(&digits, radix)
+                .map(TokenKind::LiteralInt)
+                .map_err(|_| (whole, "this number is too large to be an int".to_string()));
+        }
+
+        // digits ('.' digits)? (('e' | 'E') ('+' | '-')? digits)?
+        let mut dot = None;
+        let mut exponent = None;
+        for (i, c) in literal.iter().enumerate() {
+            match c {
+                '0'..='9' | '_' => {}
+                '.' if exponent.is_some() => {
+                    return Err((at(i), "an exponent must be a whole number".to_string()))
+                }
+                '.' if dot.is_some() => {
+                    return Err((at(i), "this number has two decimal dots".to_string()))
                 }
-                found_dot = true
+                '.' => dot = Some(i),
+                'e' | 'E' if exponent.is_none() => exponent = Some(i),
+                '+' | '-' => {}
+                c => return Err((at(i), format!("'{}' is not a digit", c))),
             }
+        }
+
+        if let Some(i) = misplaced_separator(literal, radix) {
+            return Err((at(i), "a '_' has to be followed by a digit".to_string()));
+        }
+
+        let is_digit = |i: usize| literal.get(i).is_some_and(|c| c.is_ascii_digit());
+        if let Some(i) = dot {
+            if !is_digit(i + 1) {
+                return Err((at(i), "expected digits after the decimal dot".to_string()));
+            }
+        }
+        if let Some(i) = exponent {
+            let sign = matches!(literal.get(i + 1), Some('+' | '-'));
+            if !literal[i + 1 + sign as usize..]
+                .iter()
+                .any(|c| c.is_ascii_digit())
+            {
+                return Err((at(i), "expected digits in the exponent".to_string()));
+            }
+        }
+
+        let text = literal.iter().filter(|c| **c != '_').collect::<String>();
+        if dot.is_none() && exponent.is_none() {
+            return text
+                .parse::<i64>()
+                .map(TokenKind::LiteralInt)
+                .map_err(|_| (whole, "this number is too large to be an int".to_string()));
+        }
+        match text.parse::<f64>() {
+            Ok(n) if n.is_finite() => Ok(TokenKind::LiteralFloat(n)),
+            _ => Err((whole, "this number is too large to be a float".to_string())),
+        }
+    }
+
+    /// Strings are written in double quotes and cannot span lines, a line break is written `\n`.
+    /// A `{` in a string starts an expression to interpolate, the lexer goes back to lexing the
+    /// string when it reaches the matching `}`. `continued` is whether that is the case here.
+    fn string(&mut self, continued: bool) -> TokenKind {
+        let mut value = String::new();
+        let mut error = None;
+        let interpolates = loop {
+            if self.eof() || self.nth(0) == '\n' {
+                self.is_ok = false;
+                return TokenKind::Error(LexerError::UnterminatedString);
+            }
+
+            match self.advance() {
+                Some('"') => break false,
+                Some('{') => break true,
+                Some('\\') => match self.escape() {
+                    Ok(c) => value.push(c),
+                    Err(e) => error = error.or(Some(e)),
+                },
+                Some(c) => value.push(c),
+                None => unreachable!(),
+            }
+        };
+
+        if interpolates {
+            self.interpolations.push(0);
+        }
+        if let Some(e) = error {
+            self.is_ok = false;
+            return TokenKind::Error(e);
+        }
+
+        match (continued, interpolates) {
+            (false, false) => TokenKind::LiteralString(value),
+            (false, true) => TokenKind::StringStart(value),
+            (true, true) => TokenKind::StringMiddle(value),
+            (true, false) => TokenKind::StringEnd(value),
+        }
+    }
+
+    /// The character an escape sequence stands for, the `\` has already been consumed.
+    fn escape(&mut self) -> Result<char, LexerError> {
+        if self.eof() || self.nth(0) == '\n' {
+            return Err(LexerError::InvalidEscape(' '));
+        }
+
+        match self.advance() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('\\') => Ok('\\'),
+            Some('"') => Ok('"'),
+            Some('{') => Ok('{'),
+            Some('}') => Ok('}'),
+            Some('u') => {
+                if self.nth(0) != '{' {
+                    return Err(LexerError::InvalidEscape('u'));
+                }
+                self.advance();
+
+                let mut code = String::new();
+                while !self.eof() && self.nth(0).is_ascii_hexdigit() {
+                    code.extend(self.advance());
+                }
+                if self.nth(0) != '}' {
+                    return Err(LexerError::InvalidEscape('u'));
+                }
+                self.advance();
+
+                u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(LexerError::InvalidEscape('u'))
+            }
+            Some(c) => Err(LexerError::InvalidEscape(c)),
+            None => unreachable!(),
+        }
+    }
+
+    /// Operators are the longest run of operator characters, so `<|>` is one operator
+    /// rather than `<`, `|` and `>`.
+    fn operator(&mut self) -> TokenKind {
+        while !self.eof() && OPERATOR_CHARACTERS.contains(self.nth(0)) {
             self.advance();
-            self.nth(0);
         }
 
         let lexeme = self.lexeme().expect("lexeme to be available");
-        let num = lexeme.parse::<f64>().expect("lexeme to be a number");
-        TokenKind::LiteralNumber(num)
+        match lexeme.as_str() {
+            "+" => TokenKind::Plus,
+            "-" => TokenKind::Minus,
+            "*" => TokenKind::Star,
+            "/" => TokenKind::Slash,
+            "%" => TokenKind::Percent,
+            "==" => TokenKind::EqualEqual,
+            "!=" => TokenKind::BangEqual,
+            "!" => TokenKind::Bang,
+            "<" => TokenKind::Less,
+            "<=" => TokenKind::LessEqual,
+            ">" => TokenKind::Greater,
+            ">=" => TokenKind::GreaterEqual,
+            "&&" => TokenKind::AmpAmp,
+            "||" => TokenKind::PipePipe,
+            "->" => TokenKind::Arrow,
+            "=" => TokenKind::Equal,
+            "|" => TokenKind::Pipe,
+            _ => TokenKind::Operator(lexeme),
+        }
     }
 
-    fn name(self: &mut Self) -> TokenKind {
+    fn name(&mut self) -> TokenKind {
         let mut p = self.nth(0);
         while !self.eof() && (p.is_alphabetic() || p == '_') {
             self.advance();
@@ -137,17 +373,17 @@ impl Lexer {
         TokenKind::Name(lexeme)
     }
 
-    fn comments(self: &mut Self) {
+    fn comments(&mut self) {
         while self.nth(0) == '#' {
             self.advance();
             while !self.eof() && self.nth(0) != '\n' {
                 self.advance();
             }
-            self.advance();
+            self.whitespace();
         }
     }
 
-    fn position(self: &Self) -> Position {
+    fn position(&self) -> Position {
         Position {
             begin: self.begin,
             end: self.end,
@@ -156,7 +392,7 @@ impl Lexer {
         }
     }
 
-    fn next_token(self: &mut Self) -> Option<Token> {
+    fn next_token(&mut self) -> Option<Token> {
         self.whitespace();
         self.comments();
         if self.eof() {
@@ -166,25 +402,48 @@ impl Lexer {
         self.start_column = self.column;
 
         let kind = match self.advance() {
-            Some('+') => TokenKind::Plus,
-            Some(_) if self.keyword("->") => TokenKind::Arrow,
-            Some('-') => TokenKind::Minus,
+            Some(x) if OPERATOR_CHARACTERS.contains(x) => self.operator(),
             Some(':') => TokenKind::Colon,
             Some(';') => TokenKind::Semi,
             Some(',') => TokenKind::Comma,
-            Some('=') => TokenKind::Equal,
+            Some('.') => TokenKind::Dot,
             Some('(') => TokenKind::ParenL,
             Some(')') => TokenKind::ParenR,
-            Some('{') => TokenKind::CurlyL,
-            Some('}') => TokenKind::CurlyR,
+            Some('[') => TokenKind::SquareL,
+            Some(']') => TokenKind::SquareR,
+            Some('{') => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                TokenKind::CurlyL
+            }
+            Some('}') if self.interpolations.last() == Some(&0) => {
+                self.interpolations.pop();
+                self.string(true)
+            }
+            Some('}') => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth -= 1;
+                }
+                TokenKind::CurlyR
+            }
+            Some('\\') => TokenKind::Backslash,
+            Some('"') => self.string(false),
             Some(_) if self.keyword("if") => TokenKind::KeywordIf,
+            Some(_) if self.keyword("then") => TokenKind::KeywordThen,
             Some(_) if self.keyword("else") => TokenKind::KeywordElse,
             Some(_) if self.keyword("let") => TokenKind::KeywordLet,
+            Some(_) if self.keyword("in") => TokenKind::KeywordIn,
             Some(_) if self.keyword("def") => TokenKind::KeywordDef,
+            Some(_) if self.keyword("type") => TokenKind::KeywordType,
+            Some(_) if self.keyword("infixl") => TokenKind::KeywordInfixl,
+            Some(_) if self.keyword("infixr") => TokenKind::KeywordInfixr,
+            Some(_) if self.keyword("infix") => TokenKind::KeywordInfix,
+            Some(_) if self.keyword("match") => TokenKind::KeywordMatch,
             Some(_) if self.keyword("true") => TokenKind::LiteralBool(true),
             Some(_) if self.keyword("false") => TokenKind::LiteralBool(false),
-            Some(x) if x.is_digit(10) => self.number(),
-            Some(x) if x.is_alphabetic() => self.name(),
+            Some(x) if x.is_ascii_digit() => self.number(),
+            Some(x) if x.is_alphabetic() || x == '_' => self.name(),
             Some(x) => {
                 self.is_ok = false;
                 TokenKind::Error(LexerError::UnexpectedToken(x))
@@ -199,7 +458,7 @@ impl Lexer {
         })
     }
 
-    pub fn lex(self: &mut Self) -> Vec<Token> {
+    pub fn lex(&mut self) -> Vec<Token> {
         let mut tokens = Vec::new();
         while let Some(token) = self.next_token() {
             tokens.push(token);
@@ -215,6 +474,16 @@ impl Lexer {
     }
 }
 
+/// Where a `_` in a number isn't followed by a digit or another `_`, like at the end of `1_`.
+fn misplaced_separator(literal: &[char], radix: u32) -> Option<usize> {
+    (0..literal.len()).find(|&i| {
+        literal[i] == '_'
+            && !literal
+                .get(i + 1)
+                .is_some_and(|c| c.is_digit(radix) || *c == '_')
+    })
+}
+
 pub type Input = HashMap<PathBuf, String>;
 pub type Output = HashMap<PathBuf, Vec<Token>>;
 
@@ -223,7 +492,7 @@ impl Phase<Input, Output> for Lexer {
         Lexer::new((&PathBuf::new(), &String::new()))
     }
 
-    fn run(self: &mut Self, _config: &Config, input: &Input) -> PhaseResult<Output> {
+    fn run(&mut self, _config: &Config, input: &Input) -> PhaseResult<Output> {
         let mut out = HashMap::new();
         let mut errs = Vec::new();
 
@@ -234,9 +503,10 @@ impl Phase<Input, Output> for Lexer {
             if !self.is_ok {
                 let mut errors: Vec<Message> = tokens
                     .iter()
-                    .filter(|t| matches!(t.kind, TokenKind::Error(_)))
-                    .map(|t| t.try_into().expect("token to be an erorr"))
+                    .filter_map(|t| Message::try_from(t).ok())
+                    .chain(self.errors.drain(..))
                     .collect();
+                errors.sort_by_key(|e| e.position);
                 errs.append(&mut errors);
             }
 
@@ -250,3 +520,176 @@ impl Phase<Input, Output> for Lexer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{language::token::TokenKind, phase::testing};
+
+    /// The kinds of the tokens of a source that lexes without errors, up to the end of it.
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        let (tokens, errors) = testing::lex(source);
+        assert_eq!(errors, Vec::new());
+        tokens
+            .into_iter()
+            .map(|t| t.kind)
+            .take_while(|k| *k != TokenKind::Eof)
+            .collect()
+    }
+
+    /// The message and column of each error the lexer reports.
+    fn errors(source: &str) -> Vec<(String, usize)> {
+        testing::lex(source)
+            .1
+            .into_iter()
+            .map(|m| (m.content.message, m.position.column))
+            .collect()
+    }
+
+    #[test]
+    fn operators_take_the_longest_match() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds("* / % == != < <= > >= && || ! ="),
+            [
+                Star,
+                Slash,
+                Percent,
+                EqualEqual,
+                BangEqual,
+                Less,
+                LessEqual,
+                Greater,
+                GreaterEqual,
+                AmpAmp,
+                PipePipe,
+                Bang,
+                Equal
+            ]
+        );
+    }
+
+    #[test]
+    fn strings_unescape_their_escape_sequences() {
+        assert_eq!(
+            kinds(r#""a\"b\"\n\t\\ \{\} \u{e9}\u{1F600}""#),
+            [TokenKind::LiteralString(
+                "a\"b\"\n\t\\ {} \u{e9}\u{1F600}".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn malformed_strings_are_reported_where_they_start() {
+        let error = |message: &str, column| vec![(message.to_string(), column)];
+        assert_eq!(
+            errors(r#"x = "a\qb""#),
+            error("unknown escape sequence '\\q'", 4)
+        );
+        assert_eq!(
+            errors(r#""a\u{110000}""#),
+            error("unknown escape sequence '\\u'", 0)
+        );
+        assert_eq!(errors("\"ab\n"), error("this string is never closed", 0));
+    }
+
+    #[test]
+    fn interpolated_strings_are_split_around_their_expressions() {
+        use TokenKind::*;
+        let text = |s: &str| s.to_string();
+        assert_eq!(
+            kinds(r#""a {x} b {{ y: 1 }.y} c""#),
+            [
+                StringStart(text("a ")),
+                Name(text("x")),
+                StringMiddle(text(" b ")),
+                CurlyL,
+                Name(text("y")),
+                Colon,
+                LiteralInt(1),
+                CurlyR,
+                Dot,
+                Name(text("y")),
+                StringEnd(text(" c"))
+            ]
+        );
+        assert_eq!(
+            errors(r#"def main() { "abc {1 + 2" }"#),
+            [("this string is never closed".to_string(), 24)]
+        );
+    }
+
+    #[test]
+    fn numbers_can_be_written_in_other_bases_and_with_separators() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds("0xFF 0b1010 0o17 1_000 0x_1F 1__0 1.5e3 2.5E-1"),
+            [
+                LiteralInt(255),
+                LiteralInt(10),
+                LiteralInt(15),
+                LiteralInt(1000),
+                LiteralInt(31),
+                LiteralInt(10),
+                LiteralFloat(1500.0),
+                LiteralFloat(0.25)
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_numbers_are_reported_where_they_go_wrong() {
+        let error = |message: &str, column| vec![(message.to_string(), column)];
+        assert_eq!(errors("x = 0x1G"), error("'G' is not a hex digit", 7));
+        assert_eq!(errors("0b102"), error("'2' is not a binary digit", 4));
+        assert_eq!(errors("0o"), error("expected octal digits after '0o'", 0));
+        assert_eq!(
+            errors("1_000_"),
+            error("a '_' has to be followed by a digit", 5)
+        );
+        assert_eq!(
+            errors("0xF_"),
+            error("a '_' has to be followed by a digit", 3)
+        );
+        assert_eq!(errors("1e"), error("expected digits in the exponent", 1));
+        assert_eq!(errors("2.5e-"), error("expected digits in the exponent", 3));
+        assert_eq!(
+            errors("1."),
+            error("expected digits after the decimal dot", 1)
+        );
+        assert_eq!(
+            errors("1.2.3"),
+            error("this number has two decimal dots", 3)
+        );
+        assert_eq!(
+            errors("1.5e2.5"),
+            error("an exponent must be a whole number", 5)
+        );
+        assert_eq!(
+            errors("x = 9223372036854775808"),
+            error("this number is too large to be an int", 4)
+        );
+        assert_eq!(
+            errors("0xFFFFFFFFFFFFFFFFF"),
+            error("this number is too large to be an int", 0)
+        );
+        assert_eq!(
+            errors("1e400"),
+            error("this number is too large to be a float", 0)
+        );
+    }
+
+    #[test]
+    fn numbers_with_a_dot_or_an_exponent_are_floats() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds("1 1.0 1e3 0.5E-1 0"),
+            [
+                LiteralInt(1),
+                LiteralFloat(1.0),
+                LiteralFloat(1000.0),
+                LiteralFloat(0.05),
+                LiteralInt(0)
+            ]
+        );
+    }
+}
@@ -2,7 +2,20 @@ pub mod ast_builder;
 pub mod interpreter;
 pub mod lexer;
 pub mod parser;
-pub mod phase;
+#[cfg(test)]
+mod testing;
 pub mod type_checker;
 
-pub use phase::*;
+use crate::{config::Config, message::Message};
+
+pub enum PhaseResult<R> {
+    Ok(R),
+    SoftErr(R, Vec<Message>),
+    Err(Vec<Message>),
+}
+
+/// A compiler phase.
+pub trait Phase<I, R> {
+    fn new() -> Self;
+    fn run(&mut self, config: &Config, input: &I) -> PhaseResult<R>;
+}
@@ -1,4 +1,8 @@
-use std::{cell::Cell, collections::HashMap, path::PathBuf};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     language::{
@@ -6,17 +10,21 @@ use crate::{
         token::{Position, Token, TokenKind, TokenKind::*},
     },
     message::{Content, Message, Severity},
-    phase::{Phase, PhaseResult},
+    phase::{lexer::LexerError, Phase, PhaseResult},
 };
 
 ///// GRAMMAR /////
-// file = def*
+// file = (def | type_def | fixity)*
 fn file(p: &mut Parser) {
     let m = p.open();
 
     while !p.eof() {
         if p.at(TokenKind::KeywordDef) {
             def(p)
+        } else if p.at(KeywordType) {
+            type_def(p)
+        } else if p.at(KeywordInfixl) || p.at(KeywordInfixr) || p.at(KeywordInfix) {
+            fixity(p)
         } else {
             p.advance_with_error(Message {
                 severity: Severity::Error,
@@ -39,7 +47,14 @@ fn def(p: &mut Parser) {
     let m = p.open();
 
     p.expect(KeywordDef);
-    name(p);
+    if p.at(ParenL) && p.nth(1).is_operator() {
+        // Operators are defined with their symbol in parens, `def (++)(a, b) { ... }`
+        p.expect(ParenL);
+        name(p);
+        p.expect(ParenR);
+    } else {
+        name(p);
+    }
     if p.at(ParenL) {
         params(p);
     }
@@ -48,21 +63,99 @@ fn def(p: &mut Parser) {
         type_expr(p);
     }
 
-    if p.eat(CurlyL) {
-        statement(p);
-        p.expect(CurlyR);
+    if p.at(CurlyL) {
+        block(p);
     }
 
     p.close(m, Definition);
 }
 
+// type_def = 'type' name ('(' name (',' name)* ')')? '=' constructor ('|' constructor)*
+fn type_def(p: &mut Parser) {
+    assert!(p.at(KeywordType));
+    let m = p.open();
+
+    p.expect(KeywordType);
+    if p.at(TokenKind::Name("".to_string())) {
+        name(p);
+    } else {
+        p.error_here("expected a name for the type", "here");
+    }
+    if p.at(ParenL) {
+        type_params(p);
+    }
+
+    p.expect(Equal);
+    constructor(p);
+    while p.eat(Pipe) {
+        constructor(p);
+    }
+
+    p.close(m, TypeDefinition);
+}
+
+fn type_params(p: &mut Parser) {
+    assert!(p.at(ParenL));
+    let m = p.open();
+
+    p.expect(ParenL);
+    while p.at(TokenKind::Name("".to_string())) {
+        name(p);
+        if !p.at(ParenR) {
+            p.expect(Comma);
+        }
+    }
+    p.expect(ParenR);
+
+    p.close(m, TypeParams);
+}
+
+// constructor = name ('(' type_expr (',' type_expr)* ')')?
+fn constructor(p: &mut Parser) {
+    let m = p.open();
+
+    if p.at(TokenKind::Name("".to_string())) {
+        name(p);
+    } else {
+        p.error_here("expected a constructor", "here");
+    }
+    if p.at(ParenL) {
+        type_args(p);
+    }
+
+    p.close(m, Constructor);
+}
+
+// fixity = ('infixl' | 'infixr' | 'infix') number operator
+// The declarations themselves are collected before parsing, see `Parser::declare_fixities`.
+fn fixity(p: &mut Parser) {
+    let m = p.open();
+
+    p.advance();
+    match p.nth(0) {
+        LiteralInt(n) if Fixity::precedence(n).is_some() => p.advance(),
+        LiteralInt(_) | LiteralFloat(_) => {
+            p.error_here("precedence must be a whole number from 0 to 9", "here");
+            p.advance();
+        }
+        _ => p.error_here("expected a precedence", "here"),
+    }
+    if p.nth(0).is_operator() {
+        p.advance();
+    } else {
+        p.error_here("expected an operator", "here");
+    }
+
+    p.close(m, TreeKind::Fixity);
+}
+
 fn params(p: &mut Parser) {
     assert!(p.at(TokenKind::ParenL));
     let m = p.open();
 
     p.expect(TokenKind::ParenL);
     while !p.at(TokenKind::ParenR) && !p.eof() {
-        if p.at(TokenKind::Name("".to_string())) {
+        if p.at(TokenKind::Name("".to_string())) || p.at(ParenL) {
             param(p);
         } else {
             break;
@@ -73,9 +166,10 @@ fn params(p: &mut Parser) {
     p.close(m, Params);
 }
 
+// param = pattern ':' type_expr
 fn param(p: &mut Parser) {
     let m = p.open();
-    name(p);
+    pattern(p);
     p.expect(TokenKind::Colon);
     type_expr(p);
     if !p.at(TokenKind::ParenR) {
@@ -86,38 +180,214 @@ fn param(p: &mut Parser) {
 }
 
 /////// TYPES ///////
-fn type_expr(p: &mut Parser) {
+// type_expr = type_atom ('->' type_expr)?
+fn type_expr(p: &mut Parser) -> MarkClosed {
+    let lhs = type_atom(p);
+    if !p.at(Arrow) {
+        return lhs;
+    }
+
+    let m = p.open_before(lhs);
+    p.expect(Arrow);
+    type_expr(p);
+    p.close(m, FunctionType)
+}
+
+// type_atom = name type_args? | '(' ')' | '(' type_expr ')' | '(' type_expr (',' type_expr)+ ')'
+//           | record_type
+fn type_atom(p: &mut Parser) -> MarkClosed {
+    let m = p.open();
+    if p.at(CurlyL) {
+        record_type(p);
+        return p.close(m, RecordType);
+    }
+    if p.eat(ParenL) {
+        if p.eat(ParenR) {
+            return p.close(m, Unit);
+        }
+        type_expr(p);
+        if p.at(Comma) {
+            while p.eat(Comma) {
+                type_expr(p);
+            }
+            p.expect(ParenR);
+            return p.close(m, TupleType);
+        }
+        p.expect(ParenR);
+    } else if p.at(TokenKind::Name("".to_string())) {
+        name(p);
+        if p.at(ParenL) {
+            type_args(p);
+        }
+    } else {
+        p.error_here("expected a type", "here");
+    }
+    p.close(m, TypeExpr)
+}
+
+// record_type = '{' (name ':' type_expr (',' name ':' type_expr)*)? ('|' name)? '}'
+// The name after the '|' stands for the rest of the fields of the record.
+fn record_type(p: &mut Parser) {
+    p.expect(CurlyL);
+    while p.at(TokenKind::Name("".to_string())) && p.nth(1) == Colon {
+        let m = p.open();
+        name(p);
+        p.expect(Colon);
+        type_expr(p);
+        if !p.at(CurlyR) && !p.at(Pipe) {
+            p.expect(Comma);
+        }
+        p.close(m, RecordField);
+    }
+    if p.eat(Pipe) {
+        if p.at(TokenKind::Name("".to_string())) {
+            name(p);
+        } else {
+            p.error_here("expected a name for the rest of the fields", "here");
+        }
+    }
+    p.expect(CurlyR);
+}
+
+// type_args = '(' type_expr (',' type_expr)* ')'
+fn type_args(p: &mut Parser) {
+    assert!(p.at(ParenL));
     let m = p.open();
-    name(p);
-    p.close(m, TypeExpr);
+
+    p.expect(ParenL);
+    while p.at(TokenKind::Name("".to_string())) || p.at(ParenL) || p.at(CurlyL) {
+        type_expr(p);
+        if !p.at(ParenR) {
+            p.expect(Comma);
+        }
+    }
+    p.expect(ParenR);
+
+    p.close(m, TypeArgs);
 }
 
 //////// EXPRESSIONS /////////
-fn statement(p: &mut Parser) {
-    let mut lhs = expression(p);
-    while p.at(Semi) {
+// block = '{' statement* expr '}'
+fn block(p: &mut Parser) -> MarkClosed {
+    assert!(p.at(CurlyL));
+    let m = p.open();
+
+    p.expect(CurlyL);
+    statements(p);
+    p.expect(CurlyR);
+
+    p.close(m, Block)
+}
+
+// statement = 'let' pattern '=' expr ';' | expr ';'
+fn statements(p: &mut Parser) {
+    let mut ends_in_expression = false;
+    while !p.at_end_of(CurlyR) {
+        let lhs = if p.at(KeywordLet) {
+            let m = p.open();
+            p.expect(KeywordLet);
+            binding(p);
+            if !p.at(KeywordIn) {
+                p.expect(Semi);
+                p.close(m, Statement);
+                ends_in_expression = false;
+                continue;
+            }
+            p.expect(KeywordIn);
+            expression(p);
+            p.close(m, Let)
+        } else {
+            expression(p)
+        };
+
+        if !p.at(Semi) {
+            ends_in_expression = true;
+            break;
+        }
+
         let m = p.open_before(lhs);
+        p.expect(Semi);
         p.close(m, Statement);
-        lhs = expression(p);
+        ends_in_expression = false;
+    }
+
+    if !ends_in_expression {
+        p.error_here("a block must end with an expression", "here");
     }
 }
 
+// binding = pattern '=' expr
+fn binding(p: &mut Parser) {
+    pattern(p);
+    p.expect(Equal);
+    expression(p);
+}
+
+// pattern = name | name '(' pattern (',' pattern)* ')' | '-'? literal | '(' pattern (',' pattern)* ')'
+fn pattern(p: &mut Parser) {
+    match p.nth(0) {
+        ParenL => {
+            let m = p.open();
+            pattern_list(p);
+            p.close(m, TuplePattern);
+        }
+        TokenKind::Name(_) if p.nth(1) == ParenL => {
+            let m = p.open();
+            name(p);
+            pattern_list(p);
+            p.close(m, ConstructorPattern);
+        }
+        TokenKind::Name(_) => {
+            name(p);
+        }
+        LiteralInt(_) | LiteralFloat(_) | LiteralBool(_) | LiteralString(_) => {
+            let m = p.open();
+            p.advance();
+            p.close(m, Literal);
+        }
+        Minus if matches!(p.nth(1), LiteralInt(_) | LiteralFloat(_)) => {
+            let m = p.open();
+            p.expect(Minus);
+            p.advance();
+            p.close(m, Literal);
+        }
+        _ => p.error_here("expected a pattern", "here"),
+    }
+}
+
+fn pattern_list(p: &mut Parser) {
+    p.expect(ParenL);
+    pattern(p);
+    while p.eat(Comma) {
+        pattern(p);
+    }
+    p.expect(ParenR);
+}
+
+// match_arm = pattern '->' expr
+fn match_arm(p: &mut Parser) {
+    let m = p.open();
+
+    pattern(p);
+    p.expect(Arrow);
+    expression(p);
+    if !p.at(CurlyR) {
+        p.expect(Comma);
+    }
+
+    p.close(m, MatchArm);
+}
+
 fn expression(p: &mut Parser) -> MarkClosed {
     expr_rec(p, &Eof)
 }
 
 fn expr_rec(p: &mut Parser, left: &TokenKind) -> MarkClosed {
-    let mut lhs = expr_delimited(p);
-
-    while p.at(ParenL) {
-        let m = p.open_before(lhs);
-        arg_list(p);
-        lhs = p.close(m, Call);
-    }
+    let mut lhs = expr_call(p);
 
     loop {
         let right = p.nth(0);
-        if right_binds_tighter(left, &right) {
+        if right_binds_tighter(p, left, &right) {
             let m = p.open_before(lhs);
             p.advance();
             expr_rec(p, &right);
@@ -128,12 +398,36 @@ fn expr_rec(p: &mut Parser, left: &TokenKind) -> MarkClosed {
     }
 }
 
+// Calls and field accesses bind tighter than any operator, so `-f(x).a` is `-((f(x)).a)`
+fn expr_call(p: &mut Parser) -> MarkClosed {
+    let mut lhs = expr_delimited(p);
+
+    loop {
+        if p.at(ParenL) {
+            let m = p.open_before(lhs);
+            arg_list(p);
+            lhs = p.close(m, Call);
+        } else if p.at(Dot) {
+            let m = p.open_before(lhs);
+            p.expect(Dot);
+            if p.at(TokenKind::Name("".to_string())) {
+                name(p);
+            } else {
+                p.error_here("expected a field name", "here");
+            }
+            lhs = p.close(m, Field);
+        } else {
+            break lhs;
+        }
+    }
+}
+
 fn arg_list(p: &mut Parser) {
     assert!(p.at(ParenL));
     let m = p.open();
 
     p.expect(ParenL);
-    while !p.at(ParenR) && !p.eof() {
+    while !p.at_end_of(ParenR) {
         arg(p);
     }
     p.expect(ParenR);
@@ -155,7 +449,7 @@ fn arg(p: &mut Parser) {
 fn expr_delimited(p: &mut Parser) -> MarkClosed {
     let m = p.open();
     match p.nth(0) {
-        LiteralNumber(_) | LiteralBool(_) => {
+        LiteralInt(_) | LiteralFloat(_) | LiteralBool(_) | LiteralString(_) => {
             p.advance();
             p.close(m, Literal)
         }
@@ -165,22 +459,149 @@ fn expr_delimited(p: &mut Parser) -> MarkClosed {
             p.close(m, TreeKind::Name)
         }
 
+        // interpolation = string_start expr (string_middle expr)* string_end
+        StringStart(_) => {
+            p.advance();
+            expression(p);
+            while p.at(StringMiddle(String::new())) {
+                p.advance();
+                expression(p);
+            }
+            p.expect(StringEnd(String::new()));
+            p.close(m, Interpolation)
+        }
+
+        // Prefix operators bind tighter than any infix operator, so `-a * b` is `(-a) * b`
+        Minus | Bang => {
+            p.advance();
+            expr_call(p);
+            p.close(m, Unary)
+        }
+
+        // record = '{' name ':' expr (',' name ':' expr)* ','? '}'
+        CurlyL if matches!(p.nth(1), TokenKind::Name(_)) && p.nth(2) == Colon => {
+            p.expect(CurlyL);
+            record_fields(p);
+            p.expect(CurlyR);
+            p.close(m, Record)
+        }
+
+        // record_update = '{' name '|' name ':' expr (',' name ':' expr)* ','? '}'
+        CurlyL if matches!(p.nth(1), TokenKind::Name(_)) && p.nth(2) == Pipe => {
+            p.expect(CurlyL);
+            name(p);
+            p.expect(Pipe);
+            record_fields(p);
+            p.expect(CurlyR);
+            p.close(m, RecordUpdate)
+        }
+
         CurlyL => {
             p.expect(CurlyL);
-            statement(p);
+            statements(p);
             p.expect(CurlyR);
             p.close(m, Block)
         }
 
+        KeywordIf => {
+            p.expect(KeywordIf);
+            expression(p);
+            if p.eat(KeywordThen) {
+                expression(p);
+            } else if p.at(CurlyL) {
+                block(p);
+            } else {
+                p.error_here("expected 'then' or a block", "here");
+            }
+            // A block, a record or anything else
+            p.expect(KeywordElse);
+            expression(p);
+            p.close(m, If)
+        }
+
+        KeywordLet => {
+            p.expect(KeywordLet);
+            binding(p);
+            p.expect(KeywordIn);
+            expression(p);
+            p.close(m, Let)
+        }
+
+        // match = 'match' expr '{' (match_arm (',' match_arm)* ','?)? '}'
+        KeywordMatch => {
+            p.expect(KeywordMatch);
+            expression(p);
+            p.expect(CurlyL);
+            while !p.at_end_of(CurlyR) {
+                match_arm(p);
+            }
+            p.expect(CurlyR);
+            p.close(m, Match)
+        }
+
+        // unit = '(' ')'
+        ParenL if p.nth(1) == ParenR => {
+            p.expect(ParenL);
+            p.expect(ParenR);
+            p.close(m, Unit)
+        }
+
+        // An operator on its own is a function, `(+)` is `\a b -> a + b`
+        ParenL if p.nth(1).is_operator() && p.nth(2) == ParenR => {
+            p.expect(ParenL);
+            name(p);
+            p.expect(ParenR);
+            p.close(m, Expr)
+        }
+
+        // tuple = '(' expr (',' expr)+ ')'
         ParenL => {
             p.expect(ParenL);
             expression(p);
+            if !p.at(Comma) {
+                p.expect(ParenR);
+                return p.close(m, Expr);
+            }
+            while p.eat(Comma) {
+                expression(p);
+            }
             p.expect(ParenR);
-            p.close(m, Expr)
+            p.close(m, Tuple)
+        }
+
+        // list = '[' (expr (',' expr)* ','?)? ']'
+        SquareL => {
+            p.expect(SquareL);
+            while !p.at_end_of(SquareR) {
+                expression(p);
+                if !p.at(SquareR) {
+                    p.expect(Comma);
+                }
+            }
+            p.expect(SquareR);
+            p.close(m, List)
+        }
+
+        Backslash => {
+            p.expect(Backslash);
+            if !p.at(TokenKind::Name("".to_string())) && !p.at(ParenL) {
+                p.error_here("a lambda needs at least one parameter", "here");
+            }
+            while p.at(TokenKind::Name("".to_string())) || p.at(ParenL) {
+                lambda_param(p);
+            }
+            p.expect(Arrow);
+            expression(p);
+            p.close(m, Lambda)
         }
 
         _ => {
-            if !p.eof() {
+            // The lexer has already reported the tokens it couldn't make sense of
+            if !p.at(Error(LexerError::InvalidNumber)) {
+                p.error_here("expected an expression", "here");
+            }
+            // The rest of an interpolated string is left for the string to close itself
+            if !p.eof() && !p.at(StringMiddle(String::new())) && !p.at(StringEnd(String::new())) {
                 p.advance();
             }
             p.close(m, ErrorTree)
@@ -188,27 +609,70 @@ fn expr_delimited(p: &mut Parser) -> MarkClosed {
     }
 }
 
-fn right_binds_tighter(left: &TokenKind, right: &TokenKind) -> bool {
-    fn tightness(kind: &TokenKind) -> Option<usize> {
-        [
-            // Precedence table
-            &[Plus, Minus],
-            // &[Start, Slash],
-        ]
-        .iter()
-        .position(|l| l.contains(kind))
+fn record_fields(p: &mut Parser) {
+    if !p.at(TokenKind::Name("".to_string())) {
+        p.error_here("expected a field", "here");
+    }
+    while p.at(TokenKind::Name("".to_string())) {
+        let m = p.open();
+        name(p);
+        p.expect(Colon);
+        expression(p);
+        if !p.at(CurlyR) {
+            p.expect(Comma);
+        }
+        p.close(m, RecordField);
     }
+}
 
-    let Some(right_tightness) = tightness(right) else {
+// lambda_param = name | '(' name ':' type_expr ')'
+fn lambda_param(p: &mut Parser) {
+    let m = p.open();
+
+    if p.eat(ParenL) {
+        if p.at(TokenKind::Name("".to_string())) {
+            name(p);
+        } else {
+            p.error_here("expected a parameter name", "here");
+        }
+        p.expect(Colon);
+        type_expr(p);
+        p.expect(ParenR);
+    } else {
+        name(p);
+    }
+
+    p.close(m, Param);
+}
+
+fn right_binds_tighter(p: &mut Parser, left: &TokenKind, right: &TokenKind) -> bool {
+    let Some(right_fixity) = p.fixity(right) else {
         return false;
     };
 
-    let Some(left_tightness) = tightness(left) else {
+    let Some(left_fixity) = p.fixity(left) else {
         assert!(*left == Eof);
         return true;
     };
 
-    right_tightness > left_tightness
+    if right_fixity.precedence != left_fixity.precedence {
+        return right_fixity.precedence > left_fixity.precedence;
+    }
+
+    match (left_fixity.associativity, right_fixity.associativity) {
+        (Associativity::Left, Associativity::Left) => false,
+        (Associativity::Right, Associativity::Right) => true,
+        _ => {
+            p.error_here(
+                &format!(
+                    "'{}' and '{}' cannot be chained without parentheses",
+                    left, right
+                ),
+                "add parentheses around one of them",
+            );
+            false
+        }
+    }
 }
 
 fn name(p: &mut Parser) -> MarkClosed {
@@ -218,6 +682,62 @@ fn name(p: &mut Parser) -> MarkClosed {
 }
 
 ///////// PARSER //////////
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Associativity {
+    Left,
+    Right,
+    None,
+}
+
+/// How tightly an operator binds, and which way it groups when chained with operators of the
+/// same precedence.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Fixity {
+    precedence: u8,
+    associativity: Associativity,
+}
+
+impl Fixity {
+    /// Operators that are used without being declared bind tighter than any of the built-in ones.
+    const UNDECLARED: Fixity = Fixity {
+        precedence: 9,
+        associativity: Associativity::Left,
+    };
+
+    fn precedence(n: i64) -> Option<u8> {
+        (0..=9).contains(&n).then_some(n as u8)
+    }
+
+    fn built_in() -> HashMap<String, Fixity> {
+        use Associativity::*;
+        [
+            (PipePipe, 1, Left),
+            (AmpAmp, 2, Left),
+            (EqualEqual, 3, Left),
+            (BangEqual, 3, Left),
+            (Less, 4, Left),
+            (LessEqual, 4, Left),
+            (Greater, 4, Left),
+            (GreaterEqual, 4, Left),
+            (Plus, 6, Left),
+            (Minus, 6, Left),
+            (Star, 7, Left),
+            (Slash, 7, Left),
+            (Percent, 7, Left),
+            (Operator("++".to_string()), 5, Right),
+        ]
+        .into_iter()
+        .map(|(kind, precedence, associativity)| {
+            let fixity = Fixity {
+                precedence,
+                associativity,
+            };
+            (kind.to_string(), fixity)
+        })
+        .collect()
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 enum Event {
     Open { kind: TreeKind },
@@ -242,20 +762,63 @@ pub struct Parser {
     pos: usize,
     fuel: Cell<u32>,
     events: Vec<Event>,
+    /// The fixity of every operator, by its symbol
+    fixities: HashMap<String, Fixity>,
 }
 
 impl Parser {
-    fn from(source_path: &PathBuf, tokens: &Vec<Token>) -> Self {
+    fn from(source_path: &Path, tokens: &[Token]) -> Self {
         Self {
-            source_path: source_path.clone(),
-            tokens: tokens.clone(),
+            source_path: source_path.to_path_buf(),
+            tokens: tokens.to_vec(),
             errors: Vec::new(),
             pos: 0,
             fuel: Cell::new(256),
             events: Vec::new(),
+            fixities: Fixity::built_in(),
         }
     }
 
+    /// Collect the fixity declarations of the file up front, so an operator can be used
+    /// before the line that declares it. Malformed declarations are reported by `fixity`.
+    fn declare_fixities(&mut self) {
+        for window in self.tokens.windows(3) {
+            let associativity = match window[0].kind {
+                KeywordInfixl => Associativity::Left,
+                KeywordInfixr => Associativity::Right,
+                KeywordInfix => Associativity::None,
+                _ => continue,
+            };
+            let LiteralInt(n) = window[1].kind else {
+                continue;
+            };
+            let (Some(precedence), true) = (Fixity::precedence(n), window[2].kind.is_operator())
+            else {
+                continue;
+            };
+
+            self.fixities.insert(
+                window[2].text(),
+                Fixity {
+                    precedence,
+                    associativity,
+                },
+            );
+        }
+    }
+
+    fn fixity(&self, kind: &TokenKind) -> Option<Fixity> {
+        if !kind.is_operator() {
+            return None;
+        }
+        Some(
+            self.fixities
+                .get(&kind.to_string())
+                .copied()
+                .unwrap_or(Fixity::UNDECLARED),
+        )
+    }
+
     fn open(&mut self) -> MarkOpened {
         let mark = MarkOpened {
             index: self.events.len(),
@@ -325,6 +888,15 @@ impl Parser {
         std::mem::discriminant(&self.nth(0)) == std::mem::discriminant(&kind)
     }
 
+    /// Whether a list that `closer` ends has run out, because it is there or because the
+    /// input or the interpolation the list is in ends first.
+    fn at_end_of(&self, closer: TokenKind) -> bool {
+        self.at(closer)
+            || self.eof()
+            || self.at(StringMiddle(String::new()))
+            || self.at(StringEnd(String::new()))
+    }
+
     fn eat(&mut self, kind: TokenKind) -> bool {
         if self.at(kind) {
             self.advance();
@@ -351,6 +923,19 @@ impl Parser {
         });
     }
 
+    fn error_here(&mut self, message: &str, indicator_message: &str) {
+        self.errors.push(Message {
+            severity: Severity::Error,
+            position: self.position(),
+            source_path: self.source_path.clone(),
+            content: Content {
+                message: message.to_string(),
+                indicator_message: Some(indicator_message.to_string()),
+                fix_hint: None,
+            },
+        });
+    }
+
     fn build_tree(&mut self) -> Tree {
         let mut stack = Vec::new();
         let mut tokens = self.tokens.iter();
@@ -387,6 +972,7 @@ impl Parser {
     }
 
     fn parse(&mut self) -> Tree {
+        self.declare_fixities();
         file(self);
         self.build_tree()
     }
@@ -399,7 +985,7 @@ impl Phase<Input, Output> for Parser {
         Parser::from(&PathBuf::new(), &Vec::new())
     }
 
-    fn run(self: &mut Self, _config: &crate::config::Config, input: &Input) -> PhaseResult<Output> {
+    fn run(&mut self, _config: &crate::config::Config, input: &Input) -> PhaseResult<Output> {
         let mut out = HashMap::new();
         let mut errs = Vec::new();
 
@@ -420,3 +1006,208 @@ impl Phase<Input, Output> for Parser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        language::cst::{Child, Tree, TreeKind},
+        phase::testing,
+    };
+
+    /// A tree as nested lists of its kind and children. Names and literals are just their text.
+    fn shape(child: &Child) -> String {
+        match child {
+            Child::Token(t) => t.text(),
+            Child::Tree(Tree { kind, children }) => match (kind, &children[..]) {
+                (TreeKind::Name | TreeKind::Literal, [Child::Token(t)]) => t.text(),
+                _ => format!(
+                    "({:?} {})",
+                    kind,
+                    children
+                        .iter()
+                        .map(shape)
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                ),
+            },
+        }
+    }
+
+    /// The shapes of what is in the block of `def main() { source }`, which comes after
+    /// `declarations`, and the errors.
+    fn parse_block(declarations: &str, source: &str) -> (String, Vec<String>) {
+        let source = format!("{}\ndef main() {{ {} }}", declarations, source);
+        let (file, errors) = testing::parse(&source);
+        let Some(definition) = file.children.iter().rev().find_map(|c| match c {
+            Child::Tree(t) if t.kind == TreeKind::Definition => Some(t),
+            _ => None,
+        }) else {
+            panic!("no definition in {:?}", file);
+        };
+        let Some(Child::Tree(block)) = definition.children.last() else {
+            panic!("no block in {:?}", definition);
+        };
+        let inside = &block.children[1..block.children.len() - 1];
+
+        (
+            inside.iter().map(shape).collect::<Vec<String>>().join(" "),
+            errors.into_iter().map(|e| e.content.message).collect(),
+        )
+    }
+
+    fn block(source: &str) -> String {
+        declared_block("", source)
+    }
+
+    fn declared_block(declarations: &str, source: &str) -> String {
+        let (shape, errors) = parse_block(declarations, source);
+        assert_eq!(errors, Vec::<String>::new());
+        shape
+    }
+
+    #[test]
+    fn lambdas_take_parameters_up_to_the_arrow() {
+        assert_eq!(block("\\x y -> x"), "(Lambda \\ (Param x) (Param y) -> x)");
+        assert_eq!(
+            block("\\f -> \\x -> f(x) + 1"),
+            "(Lambda \\ (Param f) -> (Lambda \\ (Param x) -> (Binary (Call f (Args ( (Arg x) ))) + 1)))"
+        );
+
+        let (_, errors) = parse_block("", "\\x x");
+        assert_eq!(errors[0], "expected '->'");
+    }
+
+    #[test]
+    fn lets_and_statements_make_up_blocks() {
+        assert_eq!(block("let x = 1 in x"), "(Let let x = 1 in x)");
+        assert_eq!(
+            block("let x = 1; x; x"),
+            "(Statement let x = 1 ;) (Statement x ;) x"
+        );
+
+        let (_, errors) = parse_block("", "let x = 1;");
+        assert_eq!(errors, ["a block must end with an expression"]);
+    }
+
+    #[test]
+    fn ifs_take_then_or_blocks_and_chain_with_else_if() {
+        assert_eq!(block("if a then b else c"), "(If if a then b else c)");
+        assert_eq!(
+            block("if a { b } else if c { d } else { e }"),
+            "(If if a (Block { b }) else (If if c (Block { d }) else (Block { e })))"
+        );
+        assert_eq!(
+            block("if a then { x: 1 } else { x: 2 }"),
+            "(If if a then (Record { (RecordField x : 1) }) else (Record { (RecordField x : 2) }))"
+        );
+        assert_eq!(
+            block("if a then { x: 1 } else { r | x: 2 }"),
+            "(If if a then (Record { (RecordField x : 1) }) else \
+             (RecordUpdate { r | (RecordField x : 2) }))"
+        );
+    }
+
+    #[test]
+    fn binary_operators_bind_by_precedence_and_to_the_left() {
+        assert_eq!(
+            block("1 + 2 * 3 < 4 && a || b"),
+            "(Binary (Binary (Binary (Binary 1 + (Binary 2 * 3)) < 4) && a) || b)"
+        );
+        assert_eq!(
+            block("a == b || c != d && e >= f"),
+            "(Binary (Binary a == b) || (Binary (Binary c != d) && (Binary e >= f)))"
+        );
+        assert_eq!(
+            block("8 / 4 / 2 - 1 % 3"),
+            "(Binary (Binary (Binary 8 / 4) / 2) - (Binary 1 % 3))"
+        );
+        assert_eq!(
+            block("a < b ++ c == s ++ t ++ u"),
+            "(Binary (Binary a < (Binary b ++ c)) == (Binary s ++ (Binary t ++ u)))"
+        );
+    }
+
+    #[test]
+    fn declared_operators_group_by_their_fixity() {
+        let declarations = "infixr 5 --\ninfixl 1 |>\ninfix 4 ~~";
+        assert_eq!(
+            declared_block(declarations, "8 -- 4 -- 3 |> f |> g"),
+            "(Binary (Binary (Binary 8 -- (Binary 4 -- 3)) |> f) |> g)"
+        );
+        assert_eq!(
+            declared_block(declarations, "a + b ~~ c * d"),
+            "(Binary (Binary a + b) ~~ (Binary c * d))"
+        );
+
+        let (_, errors) = parse_block(declarations, "1 ~~ 2 ~~ 3");
+        assert_eq!(
+            errors,
+            ["'~~' and '~~' cannot be chained without parentheses"]
+        );
+    }
+
+    #[test]
+    fn prefix_operators_bind_tighter_than_infix_ones() {
+        assert_eq!(
+            block("-2 * 3 - -1"),
+            "(Binary (Binary (Unary - 2) * 3) - (Unary - 1))"
+        );
+        assert_eq!(
+            block("!(a > b) && !c"),
+            "(Binary (Unary ! (Expr ( (Binary a > b) ))) && (Unary ! c))"
+        );
+        assert_eq!(
+            block("-f(x).y"),
+            "(Unary - (Field (Call f (Args ( (Arg x) ))) . y))"
+        );
+    }
+
+    #[test]
+    fn interpolations_hold_expressions() {
+        assert_eq!(
+            block(r#""a {x + 1} b {f(y)}""#),
+            r#"(Interpolation "a { (Binary x + 1) } b { (Call f (Args ( (Arg y) ))) }")"#
+        );
+
+        let (_, errors) = parse_block("", r#""abc {}""#);
+        assert_eq!(errors, ["expected an expression"]);
+    }
+
+    #[test]
+    fn unclosed_lists_end_with_their_interpolation() {
+        for source in [r#""{f(}""#, r#""{[}""#, r#""{match x }""#] {
+            let (_, errors) = parse_block("", source);
+            assert!(!errors.is_empty(), "{} was accepted", source);
+        }
+    }
+
+    #[test]
+    fn parentheses_group_or_make_tuples_and_unit() {
+        assert_eq!(
+            block("((1), (a, b), ())"),
+            "(Tuple ( (Expr ( 1 )) , (Tuple ( a , b )) , (Unit ( )) ))"
+        );
+    }
+
+    #[test]
+    fn match_arms_are_patterns_and_bodies() {
+        assert_eq!(
+            block("match o { None -> 0, Some((x, _)) -> x, }"),
+            "(Match match o { (MatchArm None -> 0 ,) \
+             (MatchArm (ConstructorPattern Some ( (TuplePattern ( x , _ )) )) -> x ,) })"
+        );
+        assert_eq!(
+            block("match n { -1 -> a, -2.5 -> b }"),
+            "(Match match n { (MatchArm (Literal - 1) -> a ,) (MatchArm (Literal - 2.5) -> b) })"
+        );
+    }
+
+    #[test]
+    fn records_are_built_updated_and_read() {
+        assert_eq!(
+            block("({ a: 1, b: x }, { r | a: 2 }, r.a.b)"),
+            "(Tuple ( (Record { (RecordField a : 1 ,) (RecordField b : x) }) , \
+             (RecordUpdate { r | (RecordField a : 2) }) , (Field (Field r . a) . b) ))"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! Running a source through the phases before the one a test is about.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{
+    config::Config,
+    language::{ast::Program, cst::Tree, token::Token},
+    message::Message,
+    phase::{
+        ast_builder::{self, AstBuilder},
+        lexer::Lexer,
+        parser::Parser,
+        type_checker::TypeChecker,
+        Phase, PhaseResult,
+    },
+    prelude,
+};
+
+/// Where the source of a test is said to come from.
+pub const PATH: &str = "./main.sfl";
+
+fn sources(config: &Config, source: &str) -> HashMap<PathBuf, String> {
+    let mut sources = HashMap::from([(PathBuf::from(PATH), source.to_string())]);
+    prelude::add(config, &mut sources);
+    sources
+}
+
+fn ok<R>(result: PhaseResult<R>) -> R {
+    match result {
+        PhaseResult::Ok(r) => r,
+        PhaseResult::SoftErr(_, messages) | PhaseResult::Err(messages) => {
+            panic!("phase failed with {:?}", messages)
+        }
+    }
+}
+
+/// What a phase gives back, even when it reports errors, and what it reports.
+fn messages<R>(result: PhaseResult<R>) -> (Option<R>, Vec<Message>) {
+    match result {
+        PhaseResult::Ok(r) => (Some(r), Vec::new()),
+        PhaseResult::SoftErr(r, messages) => (Some(r), messages),
+        PhaseResult::Err(messages) => (None, messages),
+    }
+}
+
+/// The tokens of a source, without the prelude, and the errors the lexer reports.
+pub fn lex(source: &str) -> (Vec<Token>, Vec<Message>) {
+    let sources = HashMap::from([(PathBuf::from(PATH), source.to_string())]);
+    let (tokens, messages) = messages(Lexer::new().run(&Config::default(), &sources));
+    (tokens.unwrap()[&PathBuf::from(PATH)].clone(), messages)
+}
+
+/// The tree of a source that lexes without errors, and the errors the parser reports.
+pub fn parse(source: &str) -> (Tree, Vec<Message>) {
+    let config = Config::default();
+    let sources = HashMap::from([(PathBuf::from(PATH), source.to_string())]);
+    let tokens = ok(Lexer::new().run(&config, &sources));
+    let (trees, messages) = messages(Parser::new().run(&config, &tokens));
+    (trees.unwrap()[&PathBuf::from(PATH)].clone(), messages)
+}
+
+/// The programs of a source and the prelude, which have to get through every phase before the
+/// type checker without errors.
+pub fn build_with(config: &Config, source: &str) -> ast_builder::Output {
+    let sources = sources(config, source);
+    let tokens = ok(Lexer::new().run(config, &sources));
+    let trees = ok(Parser::new().run(config, &tokens));
+    ok(AstBuilder::new().run(config, &trees))
+}
+
+/// The programs of a source and the prelude, going on past the errors the parser reports like
+/// a resilient run does.
+pub fn build_past_errors(source: &str) -> ast_builder::Output {
+    let config = Config::default();
+    let sources = sources(&config, source);
+    let tokens = ok(Lexer::new().run(&config, &sources));
+    let (trees, _) = messages(Parser::new().run(&config, &tokens));
+    ok(AstBuilder::new().run(&config, &trees.unwrap()))
+}
+
+pub fn build(source: &str) -> ast_builder::Output {
+    build_with(&Config::default(), source)
+}
+
+/// The program of a source, without the prelude.
+pub fn program(source: &str) -> Program {
+    let config = Config {
+        prelude: false,
+        ..Config::default()
+    };
+    build_with(&config, source)[&PathBuf::from(PATH)].clone()
+}
+
+/// The errors and warnings the type checker reports.
+pub fn check(source: &str) -> Vec<Message> {
+    messages(TypeChecker::new().run(&Config::default(), &build(source))).1
+}
+
+/// The type the type checker gives a definition in a source that has no errors or warnings.
+pub fn type_of(source: &str, name: &str) -> String {
+    let types = ok(TypeChecker::new().run(&Config::default(), &build(source)));
+    types[&PathBuf::from(PATH)][name].to_string()
+}
@@ -1,44 +1,74 @@
 use std::{
     collections::{HashMap, HashSet},
-    path::PathBuf,
+    fmt::Display,
+    path::{Path, PathBuf},
 };
 
 use crate::{
+    builtins::Builtins,
     language::{
-        ast::Ast,
-        token::{Token, TokenKind},
-        types::{Context, FreeVar, Substitutable, Substitution, TType, TypeFunc},
+        ast::{Ast, Definition, Pattern, Program, TypeAnnotation, TypeDefinition},
+        token::{Position, Token, TokenKind},
+        types::{
+            is_numeric, Context, FreeVar, Substitutable, Substitution, TType, TypeFunc, NUMERIC,
+        },
     },
     message::{Content, Message, Severity},
     phase::{Phase, PhaseResult},
+    prelude,
 };
 
 pub struct TypeChecker {
     source_path: PathBuf,
     errors: Vec<Message>,
     variable_counter: usize,
+    /// The type variables named in the annotations of the definition being checked,
+    /// and where they are first mentioned
+    type_variables: HashMap<String, (TType, Position)>,
+    /// The types that can only be `int` or `float`, and the name they come from
+    numeric: Vec<(TType, Token)>,
+    /// The data types defined by the program, and how many type arguments each takes
+    types: HashMap<String, usize>,
+    /// The constructors of each data type, in the order they are defined,
+    /// and how many fields each takes
+    constructors: HashMap<String, Vec<(String, usize)>>,
+    /// The type of every constructor
+    constructor_types: Context,
+    builtins: Builtins,
 }
 
 impl TypeChecker {
-    fn new(path: &PathBuf) -> Self {
+    fn new(path: &Path, builtins: Builtins) -> Self {
         Self {
-            source_path: path.clone(),
+            source_path: path.to_path_buf(),
             errors: Vec::new(),
             variable_counter: 0,
+            type_variables: HashMap::new(),
+            numeric: Vec::new(),
+            types: HashMap::new(),
+            constructors: HashMap::new(),
+            constructor_types: Context::new(),
+            builtins,
         }
     }
 
-    fn built_in(token: &Token) -> Option<TType> {
-        match token.kind {
-            TokenKind::Plus | TokenKind::Minus => Some(TType::Application(TypeFunc::Func {
-                input: Box::new(TType::Application(TypeFunc::Number)),
-                output: Box::new(TType::Application(TypeFunc::Func {
-                    input: Box::new(TType::Application(TypeFunc::Number)),
-                    output: Box::new(TType::Application(TypeFunc::Number)),
-                })),
-            })),
-            _ => None,
-        }
+    /// A type checker that knows the functions of `builtins`, rather than the default ones.
+    pub fn with_builtins(builtins: Builtins) -> Self {
+        TypeChecker::new(&PathBuf::new(), builtins)
+    }
+
+    fn error<T>(&mut self, position: Position, message: String) -> Result<T, ()> {
+        self.errors.push(Message {
+            severity: Severity::Error,
+            position,
+            content: Content {
+                message,
+                indicator_message: Some(" here".to_string()),
+                fix_hint: None,
+            },
+            source_path: self.source_path.clone(),
+        });
+        Err(())
     }
 
     fn variable(&mut self) -> TType {
@@ -47,40 +77,64 @@ impl TypeChecker {
         TType::Variable(v)
     }
 
-    fn instantiate(&mut self, p: &TType, mappings: Option<Substitution>) -> TType {
-        let mut m = mappings.unwrap_or_else(|| Substitution::new());
+    /// `name` is what the type belongs to, it is blamed if a numeric type turns out not to be one.
+    fn instantiate(&mut self, name: &Token, p: &TType, mappings: Option<Substitution>) -> TType {
+        let mut m = mappings.unwrap_or_default();
 
         match p {
             TType::Quantifier { variable, inner } => {
-                m.insert(variable.to_owned(), self.variable());
-                self.instantiate(inner, Some(m))
+                let v = self.variable();
+                if is_numeric(variable) {
+                    self.numeric.push((v.clone(), name.clone()));
+                }
+                m.insert(variable.to_owned(), v);
+                self.instantiate(name, inner, Some(m))
             }
             x => x.apply(&m),
         }
     }
 
-    fn generalize(&mut self, ctx: &Context, p: &TType) -> TType {
+    /// `s` is the substitution made so far. Type variables that still have to be resolved to a
+    /// numeric type are quantified as numeric ones, so they can only stand for `int` or `float`.
+    fn generalize(&mut self, ctx: &Context, s: &Substitution, p: &TType) -> TType {
+        let numeric = self
+            .numeric
+            .iter()
+            .flat_map(|(t, _)| s.apply(t).free_variables())
+            .collect::<HashSet<String>>();
         let quantifiers = p
             .free_variables()
             .difference(&ctx.free_variables())
-            .map(|v| v.to_owned())
-            .collect::<HashSet<String>>();
+            .map(|v| {
+                let quantifier = if numeric.contains(v) {
+                    format!("{}_{}", NUMERIC, v)
+                } else {
+                    v.to_owned()
+                };
+                (v.to_owned(), quantifier)
+            })
+            .collect::<Vec<(String, String)>>();
+        let renaming = Substitution::from(
+            quantifiers
+                .iter()
+                .map(|(v, quantifier)| (v.to_owned(), TType::Variable(quantifier.to_owned()))),
+        );
         quantifiers
             .into_iter()
-            .fold(p.clone(), |p, quantifier| TType::Quantifier {
-                variable: quantifier.to_owned(),
+            .fold(renaming.apply(p), |p, (_, quantifier)| TType::Quantifier {
+                variable: quantifier,
                 inner: Box::new(p),
             })
     }
 
-    fn unify(&mut self, expr: &Ast, a: &TType, b: &TType) -> Result<Substitution, ()> {
+    fn unify(&mut self, position: Position, a: &TType, b: &TType) -> Result<Substitution, ()> {
         match (a, b) {
             (TType::Variable(x), TType::Variable(y)) if x == y => Ok(Substitution::new()),
             (TType::Variable(x), _) => {
-                if a.contains(b) {
+                if b.contains(a) {
                     self.errors.push(Message {
                         severity: Severity::Error,
-                        position: expr.to_owned().into(),
+                        position,
                         content: Content {
                             message: "infinite type detected".to_string(),
                             indicator_message: Some(" here".to_string()),
@@ -88,10 +142,11 @@ impl TypeChecker {
                         },
                         source_path: self.source_path.clone(),
                     });
+                    return Err(());
                 }
                 Ok(Substitution::from([(x.to_string(), b.clone())]))
             }
-            (_, TType::Variable(_)) => self.unify(expr, b, a),
+            (_, TType::Variable(_)) => self.unify(position, b, a),
             (TType::Application(x), TType::Application(y)) => match (x, y) {
                 (
                     TypeFunc::Func {
@@ -103,15 +158,32 @@ impl TypeChecker {
                         output: o2,
                     },
                 ) => {
-                    let s1 = self.unify(expr, i1, i2)?;
-                    let s2 = self.unify(expr, &s1.apply(&**o1), &s1.apply(&**o2))?;
-                    Ok(s1.apply(&s2))
+                    let s1 = self.unify(position, i1, i2)?;
+                    let s2 = self.unify(position, &s1.apply(&**o1), &s1.apply(&**o2))?;
+                    Ok(s2.apply(&s1))
+                }
+                (
+                    TypeFunc::Data {
+                        name: n1,
+                        arguments: a1,
+                    },
+                    TypeFunc::Data {
+                        name: n2,
+                        arguments: a2,
+                    },
+                ) if n1 == n2 && a1.len() == a2.len() => self.unify_pairwise(position, a1, a2),
+                (TypeFunc::Tuple(e1), TypeFunc::Tuple(e2)) if e1.len() == e2.len() => {
+                    self.unify_pairwise(position, e1, e2)
+                }
+                (TypeFunc::List(e1), TypeFunc::List(e2)) => self.unify(position, e1, e2),
+                (TypeFunc::Record(r1), TypeFunc::Record(r2)) => {
+                    self.unify_rows(position, r1, r2, (a, b))
                 }
                 (x, y) if x == y => Ok(Substitution::new()),
                 (_, _) => {
                     self.errors.push(Message {
                         severity: Severity::Error,
-                        position: expr.to_owned().into(),
+                        position,
                         content: Content {
                             message: format!("expected `{}` but found `{}`", a, b),
                             indicator_message: Some(" here".to_string()),
@@ -125,7 +197,7 @@ impl TypeChecker {
             (_, _) => {
                 self.errors.push(Message {
                     severity: Severity::Error,
-                    position: expr.to_owned().into(),
+                    position,
                     content: Content {
                         message: format!("expected `{}` found `{}`", a, b),
                         indicator_message: Some(" here".to_string()),
@@ -138,23 +210,807 @@ impl TypeChecker {
         }
     }
 
+    /// Unify each type of `a` with the type at the same place in `b`.
+    fn unify_pairwise(
+        &mut self,
+        position: Position,
+        a: &[TType],
+        b: &[TType],
+    ) -> Result<Substitution, ()> {
+        let mut s = Substitution::new();
+        for (t1, t2) in a.iter().zip(b) {
+            let s1 = self.unify(position, &s.apply(t1), &s.apply(t2))?;
+            s = s1.apply(&s);
+        }
+        Ok(s)
+    }
+
+    /// Unify two rows that can have their fields in any order. Every field of one row is looked
+    /// up in the other, and then the rows without that field are unified. `records` are the
+    /// records the rows belong to, they are what an error is reported about.
+    fn unify_rows(
+        &mut self,
+        position: Position,
+        r1: &TType,
+        r2: &TType,
+        records: (&TType, &TType),
+    ) -> Result<Substitution, ()> {
+        match (r1, r2) {
+            (TType::Application(TypeFunc::RowExtend { label, field, rest }), _) => {
+                let Some((field2, rest2, s1)) = self.rewrite_row(r2, label) else {
+                    return self.error(
+                        position,
+                        format!("`{}` has no field '{}'", records.1, label),
+                    );
+                };
+                // { a: int | r } and { b: int | r } would need r to both have and not have a field
+                if let Some(tail) = row_tail(rest) {
+                    if s1.apply(&tail) != tail {
+                        return self.error(
+                            position,
+                            format!("expected `{}` but found `{}`", records.0, records.1),
+                        );
+                    }
+                }
+
+                let s2 = self.unify(position, &s1.apply(&**field), &s1.apply(&field2))?;
+                let s = s2.apply(&s1);
+                let s3 = self.unify_rows(position, &s.apply(&**rest), &s.apply(&rest2), records)?;
+                Ok(s3.apply(&s))
+            }
+            (_, TType::Application(TypeFunc::RowExtend { .. })) => {
+                self.unify_rows(position, r2, r1, (records.1, records.0))
+            }
+            _ => match self.unify(position, r1, r2) {
+                Ok(s) => Ok(s),
+                Err(()) => {
+                    // Report the records rather than what is left of their rows
+                    self.errors.pop();
+                    self.error(
+                        position,
+                        format!("expected `{}` but found `{}`", records.0, records.1),
+                    )
+                }
+            },
+        }
+    }
+
+    /// The type of the `label` field of a row and the row without it. A row that ends in a
+    /// variable can have any field, the substitution then makes the variable hold that field.
+    fn rewrite_row(&mut self, row: &TType, label: &str) -> Option<(TType, TType, Substitution)> {
+        match row {
+            TType::Application(TypeFunc::RowExtend {
+                label: l,
+                field,
+                rest,
+            }) => {
+                if l == label {
+                    return Some(((**field).clone(), (**rest).clone(), Substitution::new()));
+                }
+                let (found, rest, s) = self.rewrite_row(rest, label)?;
+                let rest = TType::Application(TypeFunc::RowExtend {
+                    label: l.to_owned(),
+                    field: Box::new(s.apply(&**field)),
+                    rest: Box::new(rest),
+                });
+                Some((found, rest, s))
+            }
+            TType::Variable(v) => {
+                let field = self.variable();
+                let rest = self.variable();
+                let extended = row_extend(label, field.clone(), rest.clone());
+                Some((field, rest, Substitution::from([(v.to_owned(), extended)])))
+            }
+            _ => None,
+        }
+    }
+
+    /// The type of a record with these fields, on top of the fields of `rest`.
+    /// A field that is given twice is an error.
+    fn record_type(&mut self, fields: Vec<(&Token, TType)>, rest: TType) -> Result<TType, ()> {
+        let mut seen = HashSet::new();
+        for (label, _) in &fields {
+            if !seen.insert(label.text()) {
+                return self.error(label.position, format!("'{}' is given twice", label.text()));
+            }
+        }
+
+        let row = fields.into_iter().rev().fold(rest, |rest, (label, field)| {
+            row_extend(&label.text(), field, rest)
+        });
+        Ok(TType::Application(TypeFunc::Record(Box::new(row))))
+    }
+
+    /// Infer the definitions of a program one group of mutually recursive definitions at a time,
+    /// starting with the groups that depend on nothing else. Within a group the definitions are
+    /// monomorphic, they are only generalized once the whole group has been inferred.
+    /// `base` is what the program can use without defining it, the program's own definitions
+    /// take the place of anything in it with the same name.
+    fn check_program(&mut self, program: &Program, base: &Context) -> Result<Context, ()> {
+        let mut indices = HashMap::new();
+        for (i, definition) in program.definitions.iter().enumerate() {
+            let name = definition.name.text();
+            if indices.insert(name.clone(), i).is_some() {
+                self.errors.push(Message {
+                    severity: Severity::Error,
+                    position: definition.name.position,
+                    content: Content {
+                        message: format!("'{}' is defined more than once", name),
+                        indicator_message: Some(" redefined here".to_string()),
+                        fix_hint: None,
+                    },
+                    source_path: self.source_path.clone(),
+                });
+                return Err(());
+            }
+        }
+
+        let values = program
+            .definitions
+            .iter()
+            .map(|d| d.value())
+            .collect::<Vec<Ast>>();
+        let dependencies = values
+            .iter()
+            .map(|v| {
+                v.free_names()
+                    .iter()
+                    .filter_map(|n| indices.get(n).copied())
+                    .collect()
+            })
+            .collect::<Vec<Vec<usize>>>();
+
+        let mut ctx = base.clone();
+        ctx.extend(self.check_types(&program.types)?);
+        for group in strongly_connected_components(&dependencies) {
+            let variables = group
+                .iter()
+                .map(|&i| (i, self.variable()))
+                .collect::<Vec<(usize, TType)>>();
+
+            let mut group_ctx = ctx.clone();
+            for (i, v) in &variables {
+                group_ctx.insert(program.definitions[*i].name.text(), v.clone());
+            }
+
+            let mut s = Substitution::new();
+            let mut type_variables = Vec::new();
+            for (i, v) in &variables {
+                let definition = &program.definitions[*i];
+                self.type_variables.clear();
+                let (s1, t) = self.w(&s.apply(&group_ctx), &values[*i])?;
+                s = s1.apply(&s);
+                let s2 = self.check_return_type(definition, &s, &t)?;
+                s = s2.apply(&s);
+                let s3 = self.unify(definition.name.position, &s.apply(v), &s.apply(&t))?;
+                s = s3.apply(&s);
+                type_variables.push(std::mem::take(&mut self.type_variables));
+            }
+
+            // What the definitions can be used at is left to where they are used
+            let generalized = variables
+                .iter()
+                .flat_map(|(_, v)| s.apply(v).free_variables())
+                .collect::<HashSet<String>>();
+            s = self.resolve_numeric(s, &generalized)?;
+            for declared in &type_variables {
+                self.check_type_variables(&s, declared)?;
+            }
+
+            ctx = s.apply(&ctx);
+            for (i, v) in &variables {
+                let t = self.generalize(&ctx, &s, &s.apply(v));
+                ctx.insert(program.definitions[*i].name.text(), t);
+            }
+        }
+
+        Ok(ctx)
+    }
+
+    /// The types of the constructors of the data types a program defines.
+    /// `type Option(a) = None | Some(a)` gives `None: ∀a Option(a)` and `Some: ∀a a -> Option(a)`.
+    fn check_types(&mut self, types: &[TypeDefinition]) -> Result<Context, ()> {
+        for t in types {
+            if self.types.insert(t.name.text(), t.params.len()).is_some() {
+                self.errors.push(Message {
+                    severity: Severity::Error,
+                    position: t.name.position,
+                    content: Content {
+                        message: format!("type '{}' is defined more than once", t.name.text()),
+                        indicator_message: Some(" redefined here".to_string()),
+                        fix_hint: None,
+                    },
+                    source_path: self.source_path.clone(),
+                });
+                return Err(());
+            }
+        }
+
+        let mut ctx = Context::new();
+        for t in types {
+            let params = t
+                .params
+                .iter()
+                .map(|p| (p.text(), (self.variable(), p.position)))
+                .collect::<HashMap<String, (TType, Position)>>();
+            let data = TType::Application(TypeFunc::Data {
+                name: t.name.text(),
+                arguments: t
+                    .params
+                    .iter()
+                    .map(|p| params[&p.text()].0.clone())
+                    .collect(),
+            });
+
+            for constructor in &t.constructors {
+                self.type_variables = params.clone();
+                let fields = constructor
+                    .fields
+                    .iter()
+                    .map(|f| self.annotation_type(f))
+                    .collect::<Result<Vec<TType>, ()>>()?;
+
+                let unbound = self
+                    .type_variables
+                    .iter()
+                    .filter(|(name, _)| !params.contains_key(*name))
+                    .min_by_key(|(_, (_, position))| *position);
+                if let Some((name, (_, position))) = unbound {
+                    self.errors.push(Message {
+                        severity: Severity::Error,
+                        position: *position,
+                        content: Content {
+                            message: format!(
+                                "'{}' is not a type parameter of '{}'",
+                                name,
+                                t.name.text()
+                            ),
+                            indicator_message: Some(" here".to_string()),
+                            fix_hint: Some(format!(
+                                "declare it as a parameter, `type {}({})`",
+                                t.name.text(),
+                                t.params
+                                    .iter()
+                                    .map(|p| p.text())
+                                    .chain([name.to_string()])
+                                    .collect::<Vec<String>>()
+                                    .join(", ")
+                            )),
+                        },
+                        source_path: self.source_path.clone(),
+                    });
+                    return Err(());
+                }
+
+                // Some(a) is a function a -> Option(a) that works for any a
+                let function = fields
+                    .into_iter()
+                    .rev()
+                    .fold(data.clone(), |t, field| TType::function(field, t));
+                let scheme = self.generalize(&Context::new(), &Substitution::new(), &function);
+
+                let name = constructor.name.text();
+                self.constructors
+                    .entry(t.name.text())
+                    .or_default()
+                    .push((name.clone(), constructor.fields.len()));
+                if ctx.insert(name.clone(), scheme).is_some() {
+                    self.errors.push(Message {
+                        severity: Severity::Error,
+                        position: constructor.name.position,
+                        content: Content {
+                            message: format!("constructor '{}' is defined more than once", name),
+                            indicator_message: Some(" redefined here".to_string()),
+                            fix_hint: None,
+                        },
+                        source_path: self.source_path.clone(),
+                    });
+                    return Err(());
+                }
+            }
+        }
+
+        self.type_variables.clear();
+        self.constructor_types = ctx.clone();
+        Ok(ctx)
+    }
+
+    /// Make sure every type that has to be numeric is `int` or `float`.
+    /// The ones that could still be either are taken to be `int`, unless they are `generalized`.
+    fn resolve_numeric(
+        &mut self,
+        mut s: Substitution,
+        generalized: &HashSet<String>,
+    ) -> Result<Substitution, ()> {
+        for (t, name) in std::mem::take(&mut self.numeric) {
+            match s.apply(&t) {
+                TType::Application(TypeFunc::Int | TypeFunc::Float) => {}
+                TType::Variable(v) if generalized.contains(&v) => {}
+                TType::Variable(_) => {
+                    let s1 = self.unify(
+                        name.position,
+                        &TType::Application(TypeFunc::Int),
+                        &s.apply(&t),
+                    )?;
+                    s = s1.apply(&s);
+                }
+                t => {
+                    self.errors.push(Message {
+                        severity: Severity::Error,
+                        position: name.position,
+                        content: Content {
+                            message: format!(
+                                "'{}' works on `int` and `float`, but not `{}`",
+                                name.text(),
+                                t
+                            ),
+                            indicator_message: Some(" here".to_string()),
+                            fix_hint: None,
+                        },
+                        source_path: self.source_path.clone(),
+                    });
+                    return Err(());
+                }
+            }
+        }
+
+        Ok(s)
+    }
+
+    /// Unify the declared return type of a definition with the output of its inferred type,
+    /// both with what has been inferred so far, `s`, applied.
+    fn check_return_type(
+        &mut self,
+        definition: &Definition,
+        s: &Substitution,
+        t: &TType,
+    ) -> Result<Substitution, ()> {
+        let Some(annotation) = &definition.return_type else {
+            return Ok(Substitution::new());
+        };
+        let declared = s.apply(&self.annotation_type(annotation)?);
+
+        let t = s.apply(t);
+        let mut output = &t;
+        // A definition without parameters still takes `()`
+        for _ in 0..definition.params.len().max(1) {
+            let TType::Application(TypeFunc::Func { output: o, .. }) = output else {
+                break;
+            };
+            output = o;
+        }
+
+        self.unify(annotation.position(), &declared, output)
+    }
+
+    /// The type a type annotation stands for.
+    fn annotation_type(&mut self, annotation: &TypeAnnotation) -> Result<TType, ()> {
+        match annotation {
+            TypeAnnotation::Name(t) => match t.text().as_str() {
+                "int" => Ok(TType::Application(TypeFunc::Int)),
+                "float" => Ok(TType::Application(TypeFunc::Float)),
+                "bool" => Ok(TType::Application(TypeFunc::Bool)),
+                "string" => Ok(TType::Application(TypeFunc::String)),
+                name if name.starts_with(char::is_lowercase) => {
+                    if let Some((v, _)) = self.type_variables.get(name) {
+                        return Ok(v.clone());
+                    }
+                    let v = self.variable();
+                    self.type_variables
+                        .insert(name.to_string(), (v.clone(), t.position));
+                    Ok(v)
+                }
+                _ => self.data_type(t, &[]),
+            },
+            TypeAnnotation::Application(t, arguments) => self.data_type(t, arguments),
+            TypeAnnotation::Unit(_) => Ok(TType::Application(TypeFunc::Unit)),
+            TypeAnnotation::Function(input, output) => Ok(TType::Application(TypeFunc::Func {
+                input: Box::new(self.annotation_type(input)?),
+                output: Box::new(self.annotation_type(output)?),
+            })),
+            TypeAnnotation::Tuple(elements) => Ok(TType::Application(TypeFunc::Tuple(
+                elements
+                    .iter()
+                    .map(|e| self.annotation_type(e))
+                    .collect::<Result<Vec<TType>, ()>>()?,
+            ))),
+            TypeAnnotation::Record(fields, rest) => {
+                let rest = match rest {
+                    Some(rest) => self.annotation_type(&TypeAnnotation::Name(rest.clone()))?,
+                    None => TType::Application(TypeFunc::RowEmpty),
+                };
+                let fields = fields
+                    .iter()
+                    .map(|(label, field)| Ok((label, self.annotation_type(field)?)))
+                    .collect::<Result<Vec<(&Token, TType)>, ()>>()?;
+                self.record_type(fields, rest)
+            }
+        }
+    }
+
+    /// The names a pattern binds and their types, when it is matched against a value of type `t`.
+    /// The types of the names still have to have the returned substitution applied to them.
+    fn check_pattern(
+        &mut self,
+        pattern: &Pattern,
+        t: &TType,
+    ) -> Result<(Substitution, Vec<(Token, TType)>), ()> {
+        let (elements, mut s, fields) = match pattern {
+            Pattern::Name(name) => {
+                return Ok((Substitution::new(), vec![(name.clone(), t.clone())]))
+            }
+            Pattern::Wildcard(_) => return Ok((Substitution::new(), Vec::new())),
+            Pattern::Literal(l) => {
+                let Some(literal) = literal_type(&l.kind) else {
+                    return Err(());
+                };
+                return Ok((self.unify(l.position, t, &literal)?, Vec::new()));
+            }
+            Pattern::Tuple(elements, position) => {
+                match t {
+                    TType::Application(TypeFunc::Tuple(types)) if types.len() != elements.len() => {
+                        return self.error(
+                            *position,
+                            format!(
+                                "this pattern takes apart {} values, but `{}` has {}",
+                                elements.len(),
+                                t,
+                                types.len()
+                            ),
+                        );
+                    }
+                    TType::Application(TypeFunc::Tuple(_)) | TType::Variable(_) => {}
+                    t => {
+                        return self.error(
+                            *position,
+                            format!(
+                                "this pattern takes apart a tuple, but `{}` is not a tuple",
+                                t
+                            ),
+                        );
+                    }
+                }
+
+                let variables = elements
+                    .iter()
+                    .map(|_| self.variable())
+                    .collect::<Vec<TType>>();
+                let s = self.unify(
+                    *position,
+                    &TType::Application(TypeFunc::Tuple(variables.clone())),
+                    t,
+                )?;
+                (elements, s, variables)
+            }
+            Pattern::Constructor(name, elements, position) => {
+                let Some(scheme) = self.constructor_types.get(&name.text()).cloned() else {
+                    return self.error(
+                        name.position,
+                        format!("unknown constructor '{}'", name.text()),
+                    );
+                };
+
+                // Some: a -> Option(a) takes apart an Option(a) into an a
+                let mut constructed = self.instantiate(name, &scheme, None);
+                let mut fields = Vec::new();
+                while let TType::Application(TypeFunc::Func { input, output }) = constructed {
+                    fields.push(*input);
+                    constructed = *output;
+                }
+                if fields.len() != elements.len() {
+                    return self.error(
+                        *position,
+                        format!(
+                            "'{}' has {} field{}, but this pattern has {}",
+                            name.text(),
+                            fields.len(),
+                            if fields.len() == 1 { "" } else { "s" },
+                            elements.len()
+                        ),
+                    );
+                }
+
+                let s = self.unify(*position, t, &constructed)?;
+                (elements, s, fields)
+            }
+        };
+
+        let mut bindings = Vec::new();
+        for (element, field) in elements.iter().zip(&fields) {
+            let (s1, names) = self.check_pattern(element, &s.apply(field))?;
+            s = s1.apply(&s);
+            bindings.extend(names);
+        }
+
+        Ok((s, bindings))
+    }
+
+    /// Patterns that bind values, like the ones of a `let` or a parameter, have to match every
+    /// value they can be given.
+    fn check_irrefutable(&mut self, pattern: &Pattern) -> Result<(), ()> {
+        let Some(missing) = self.missing(&[vec![Shape::from(pattern)]], 1) else {
+            return Ok(());
+        };
+
+        self.errors.push(Message {
+            severity: Severity::Error,
+            position: pattern.position(),
+            content: Content {
+                message: format!("this pattern does not match `{}`", missing[0]),
+                indicator_message: Some(" here".to_string()),
+                fix_hint: Some("use a `match` to handle the values it doesn't match".to_string()),
+            },
+            source_path: self.source_path.clone(),
+        });
+        Err(())
+    }
+
+    /// Warn about the arms of a match that are never reached, since the arms before them already
+    /// match everything they do, and about values that none of the arms match.
+    fn check_match(&mut self, keyword: &Token, patterns: &[&Pattern]) {
+        let mut rows = Vec::new();
+        let mut catch_all = None;
+        for pattern in patterns {
+            let row = vec![Shape::from(*pattern)];
+            if !self.useful(&rows, &row) {
+                self.errors.push(Message {
+                    severity: Severity::Warning,
+                    position: pattern.position(),
+                    content: Content {
+                        message: "this arm is never reached".to_string(),
+                        indicator_message: Some(" here".to_string()),
+                        fix_hint: Some("the arms before it match everything it does".to_string()),
+                    },
+                    source_path: self.source_path.clone(),
+                });
+
+                if let Some(position) = catch_all.take() {
+                    self.errors.push(Message {
+                        severity: Severity::Hint,
+                        position,
+                        content: Content {
+                            message: "this arm matches every value".to_string(),
+                            indicator_message: Some(" so no arm after it is reached".to_string()),
+                            fix_hint: None,
+                        },
+                        source_path: self.source_path.clone(),
+                    });
+                }
+            } else if row[0] == Shape::Any {
+                catch_all = Some(pattern.position());
+            }
+            rows.push(row);
+        }
+
+        if let Some(missing) = self.missing(&rows, 1) {
+            self.errors.push(Message {
+                severity: Severity::Warning,
+                position: keyword.position,
+                content: Content {
+                    message: format!("this match does not handle `{}`", missing[0]),
+                    indicator_message: Some(" here".to_string()),
+                    fix_hint: Some(
+                        "add an arm for it, or a `_` arm for every value that is left".to_string(),
+                    ),
+                },
+                source_path: self.source_path.clone(),
+            });
+        }
+    }
+
+    /// Whether a row of shapes matches a value that none of the rows match.
+    /// See "Warnings for pattern matching" by Luc Maranget.
+    fn useful(&self, rows: &[Vec<Shape>], row: &[Shape]) -> bool {
+        let Some((first, rest)) = row.split_first() else {
+            return rows.is_empty();
+        };
+
+        match first {
+            Shape::Head(head, fields) => {
+                let row = fields.iter().chain(rest).cloned().collect::<Vec<Shape>>();
+                self.useful(&specialize(rows, head, fields.len()), &row)
+            }
+            Shape::Any => match self.column(rows) {
+                Column::Complete(heads) => heads.iter().any(|(head, arity)| {
+                    let row = vec![Shape::Any; *arity]
+                        .into_iter()
+                        .chain(rest.iter().cloned())
+                        .collect::<Vec<Shape>>();
+                    self.useful(&specialize(rows, head, *arity), &row)
+                }),
+                Column::Incomplete(_) => self.useful(&default(rows), rest),
+            },
+        }
+    }
+
+    /// A value that none of the rows match, as the shape of each of its columns.
+    fn missing(&self, rows: &[Vec<Shape>], columns: usize) -> Option<Vec<Shape>> {
+        if columns == 0 {
+            return rows.is_empty().then(Vec::new);
+        }
+
+        match self.column(rows) {
+            Column::Complete(heads) => heads.into_iter().find_map(|(head, arity)| {
+                let mut fields =
+                    self.missing(&specialize(rows, &head, arity), arity + columns - 1)?;
+                let rest = fields.split_off(arity);
+                Some(
+                    [Shape::Head(head, fields)]
+                        .into_iter()
+                        .chain(rest)
+                        .collect(),
+                )
+            }),
+            Column::Incomplete(example) => {
+                let mut rest = self.missing(&default(rows), columns - 1)?;
+                let first = example.map_or(Shape::Any, |(head, arity)| {
+                    Shape::Head(head, vec![Shape::Any; arity])
+                });
+                rest.insert(0, first);
+                Some(rest)
+            }
+        }
+    }
+
+    /// Whether the heads the first column of the rows starts with cover every value of its type.
+    fn column(&self, rows: &[Vec<Shape>]) -> Column {
+        let mut heads: Vec<(Head, usize)> = Vec::new();
+        for row in rows {
+            if let Some(Shape::Head(head, fields)) = row.first() {
+                if !heads.iter().any(|(h, _)| h == head) {
+                    heads.push((head.clone(), fields.len()));
+                }
+            }
+        }
+
+        let Some(signature) = heads
+            .first()
+            .and_then(|(h, arity)| self.signature(h, *arity))
+        else {
+            return Column::Incomplete(None);
+        };
+        match signature
+            .iter()
+            .find(|(head, _)| !heads.iter().any(|(h, _)| h == head))
+        {
+            Some(missing) => Column::Incomplete(Some(missing.clone())),
+            None => Column::Complete(signature),
+        }
+    }
+
+    /// All the heads of the type that `head` is a head of, and how many fields each has.
+    /// Ints, floats and strings have too many values to list.
+    fn signature(&self, head: &Head, arity: usize) -> Option<Vec<(Head, usize)>> {
+        match head {
+            Head::Tuple => Some(vec![(Head::Tuple, arity)]),
+            Head::Bool(_) => Some(vec![(Head::Bool(true), 0), (Head::Bool(false), 0)]),
+            Head::Literal(_) => None,
+            Head::Constructor(name) => self
+                .constructors
+                .values()
+                .find(|constructors| constructors.iter().any(|(c, _)| c == name))
+                .map(|constructors| {
+                    constructors
+                        .iter()
+                        .map(|(c, fields)| (Head::Constructor(c.clone()), *fields))
+                        .collect()
+                }),
+        }
+    }
+
+    /// The type a data type annotation like `Option(int)` stands for.
+    fn data_type(&mut self, name: &Token, arguments: &[TypeAnnotation]) -> Result<TType, ()> {
+        let message = match self.types.get(&name.text()) {
+            Some(&arity) if arity == arguments.len() => {
+                let arguments = arguments
+                    .iter()
+                    .map(|a| self.annotation_type(a))
+                    .collect::<Result<Vec<TType>, ()>>()?;
+                return Ok(TType::Application(TypeFunc::Data {
+                    name: name.text(),
+                    arguments,
+                }));
+            }
+            Some(&arity) => format!(
+                "'{}' takes {} type argument{} but was given {}",
+                name.text(),
+                arity,
+                if arity == 1 { "" } else { "s" },
+                arguments.len()
+            ),
+            // Programs can define their own `List`, which takes the place of the built-in one
+            None if name.text() == "List" => match arguments {
+                [element] => {
+                    let element = self.annotation_type(element)?;
+                    return Ok(TType::Application(TypeFunc::List(Box::new(element))));
+                }
+                _ => format!(
+                    "'List' takes 1 type argument but was given {}",
+                    arguments.len()
+                ),
+            },
+            None => format!("unknown type '{}'", name.text()),
+        };
+
+        self.errors.push(Message {
+            severity: Severity::Error,
+            position: name.position,
+            content: Content {
+                message,
+                indicator_message: Some(" here".to_string()),
+                fix_hint: None,
+            },
+            source_path: self.source_path.clone(),
+        });
+        Err(())
+    }
+
+    /// Type variables in annotations stand for any type, so inference must not have narrowed one
+    /// down to a specific type or made two of them the same.
+    fn check_type_variables(
+        &mut self,
+        s: &Substitution,
+        type_variables: &HashMap<String, (TType, Position)>,
+    ) -> Result<(), ()> {
+        let mut seen: HashMap<String, &String> = HashMap::new();
+        let mut type_variables = type_variables.iter().collect::<Vec<_>>();
+        type_variables.sort_by_key(|(_, (_, position))| *position);
+
+        for (name, (v, position)) in type_variables {
+            let message = match s.apply(v) {
+                TType::Variable(inferred) => match seen.insert(inferred, name) {
+                    Some(other) => format!(
+                        "'{}' and '{}' stand for any two types, but they have to be the same",
+                        other, name
+                    ),
+                    None => continue,
+                },
+                t => format!("'{}' stands for any type, but it has to be `{}`", name, t),
+            };
+
+            self.errors.push(Message {
+                severity: Severity::Error,
+                position: *position,
+                content: Content {
+                    message,
+                    indicator_message: Some(" declared here".to_string()),
+                    fix_hint: None,
+                },
+                source_path: self.source_path.clone(),
+            });
+            return Err(());
+        }
+
+        Ok(())
+    }
+
+    /// Infer the values of the fields of a record, adding to the substitution `s` made so far.
+    fn w_fields<'a>(
+        &mut self,
+        ctx: &Context,
+        s: &mut Substitution,
+        fields: &'a [(Token, Ast)],
+    ) -> Result<Vec<(&'a Token, TType)>, ()> {
+        let mut types = Vec::new();
+        for (label, value) in fields {
+            let (s1, t) = self.w(&s.apply(ctx), value)?;
+            *s = s1.apply(s);
+            types.push((label, t));
+        }
+
+        Ok(types.into_iter().map(|(l, t)| (l, s.apply(&t))).collect())
+    }
+
     fn w(&mut self, ctx: &Context, expr: &Ast) -> Result<(Substitution, TType), ()> {
         match expr {
             Ast::Expr(e) => self.w(ctx, e),
-            Ast::Literal(l) => match l.kind {
-                TokenKind::LiteralNumber(_) => {
-                    Ok((Substitution::new(), TType::Application(TypeFunc::Number)))
-                }
-                TokenKind::LiteralBool(_) => {
-                    Ok((Substitution::new(), TType::Application(TypeFunc::Bool)))
-                }
-                _ => Err(()),
+            Ast::Literal(l) => match literal_type(&l.kind) {
+                Some(t) => Ok((Substitution::new(), t)),
+                None => Err(()),
             },
             Ast::Name(n) => {
                 if let Some(t) = ctx.get(&n.text()) {
-                    Ok((Substitution::new(), self.instantiate(t, None)))
-                } else if let Some(t) = TypeChecker::built_in(n) {
-                    Ok((Substitution::new(), self.instantiate(&t, None)))
+                    Ok((Substitution::new(), self.instantiate(n, t, None)))
                 } else {
                     self.errors.push(Message {
                         severity: Severity::Error,
@@ -169,11 +1025,28 @@ impl TypeChecker {
                     Err(())
                 }
             }
-            Ast::Abstraction(n, e) => {
+            Ast::Abstraction(pattern, annotation, e) => {
                 let var = self.variable();
-                let mut ctx_with_var = ctx.clone();
-                ctx_with_var.insert(n.text(), var.clone());
+                // The declared type is taken before anything else, so a pattern that doesn't
+                // fit it or a body that misuses the parameter is blamed, and not the annotation
+                let s0 = match annotation {
+                    Some(annotation) => {
+                        let declared = self.annotation_type(annotation)?;
+                        self.unify(pattern.position(), &declared, &var)?
+                    }
+                    None => Substitution::new(),
+                };
+                let (s1, bindings) = self.check_pattern(pattern, &s0.apply(&var))?;
+                self.check_irrefutable(pattern)?;
+                let s0 = s1.apply(&s0);
+
+                let mut ctx_with_var = s0.apply(ctx);
+                for (name, t) in bindings {
+                    ctx_with_var.insert(name.text(), s0.apply(&t));
+                }
                 let (s, e_t) = self.w(&ctx_with_var, e)?;
+                let s = s.apply(&s0);
+
                 let t = s.apply(&TType::Application(TypeFunc::Func {
                     input: Box::new(var),
                     output: Box::new(e_t),
@@ -185,7 +1058,7 @@ impl TypeChecker {
                 let (s2, e2_t) = self.w(&s1.apply(ctx), e2)?;
                 let var = self.variable();
                 let s3 = self.unify(
-                    e2,
+                    e2.position(),
                     &s2.apply(&e1_t),
                     &TType::Application(TypeFunc::Func {
                         input: Box::new(e2_t),
@@ -194,15 +1067,149 @@ impl TypeChecker {
                 )?;
                 Ok((s3.apply(&s2.apply(&s1)), s3.apply(&var)))
             }
-            Ast::Let(n, e1, e2) => {
+            Ast::Let(pattern, e1, e2) => {
                 let (s1, e1_t) = self.w(ctx, e1)?;
-                let mut new_ctx = s1.apply(ctx);
-                new_ctx.insert(n.text(), self.generalize(&new_ctx, &e1_t));
-                let (s2, e2_t) = self.w(&new_ctx, e2)?;
+                let (s2, bindings) = self.check_pattern(pattern, &e1_t)?;
+                self.check_irrefutable(pattern)?;
+                let s = s2.apply(&s1);
+
+                let outer_ctx = s.apply(ctx);
+                let mut new_ctx = outer_ctx.clone();
+                for (name, t) in bindings {
+                    let t = self.generalize(&outer_ctx, &s, &s.apply(&t));
+                    new_ctx.insert(name.text(), t);
+                }
+                let (s3, e2_t) = self.w(&new_ctx, e2)?;
 
-                Ok((s2.apply(&s1), e2_t))
+                Ok((s3.apply(&s), e2_t))
             }
+            Ast::List(_, elements) => {
+                let element_t = self.variable();
+                let mut s = Substitution::new();
+                for element in elements {
+                    let (s1, t) = self.w(&s.apply(ctx), element)?;
+                    s = s1.apply(&s);
+                    let s2 = self.unify(element.position(), &s.apply(&element_t), &t)?;
+                    s = s2.apply(&s);
+                }
+
+                let t = TType::Application(TypeFunc::List(Box::new(s.apply(&element_t))));
+                Ok((s, t))
+            }
+            Ast::Match(keyword, e, arms) => {
+                let (mut s, e_t) = self.w(ctx, e)?;
+                let result = self.variable();
+                for (pattern, body) in arms {
+                    let (s1, bindings) = self.check_pattern(pattern, &s.apply(&e_t))?;
+                    s = s1.apply(&s);
+
+                    let mut arm_ctx = s.apply(ctx);
+                    for (name, t) in bindings {
+                        arm_ctx.insert(name.text(), s.apply(&t));
+                    }
+                    let (s2, body_t) = self.w(&arm_ctx, body)?;
+                    s = s2.apply(&s);
+                    let s3 = self.unify(body.position(), &s.apply(&result), &body_t)?;
+                    s = s3.apply(&s);
+                }
+
+                let patterns = arms.iter().map(|(p, _)| p).collect::<Vec<&Pattern>>();
+                self.check_match(keyword, &patterns);
+                let t = s.apply(&result);
+                Ok((s, t))
+            }
+            Ast::Tuple(elements) => {
+                let mut s = Substitution::new();
+                let mut types = Vec::new();
+                for element in elements {
+                    let (s1, t) = self.w(&s.apply(ctx), element)?;
+                    s = s1.apply(&s);
+                    types.push(t);
+                }
+
+                let types = types.iter().map(|t| s.apply(t)).collect();
+                Ok((s, TType::Application(TypeFunc::Tuple(types))))
+            }
+            Ast::Record(fields) => {
+                let mut s = Substitution::new();
+                let fields = self.w_fields(ctx, &mut s, fields)?;
+                let t = self.record_type(fields, TType::Application(TypeFunc::RowEmpty))?;
+                Ok((s, t))
+            }
+            Ast::RecordUpdate(e, fields) => {
+                // The record has to have every field already, with the type it is given
+                let (mut s, e_t) = self.w(ctx, e)?;
+                let fields = self.w_fields(ctx, &mut s, fields)?;
+                let position = fields.first().map_or(e.position(), |(l, _)| l.position);
+                let rest = self.variable();
+                let updated = self.record_type(fields, rest)?;
+                let s1 = self.unify(position, &s.apply(&e_t), &updated)?;
+                let s = s1.apply(&s);
+                let t = s.apply(&e_t);
+                Ok((s, t))
+            }
+            Ast::Field(e, label) => {
+                let (s1, e_t) = self.w(ctx, e)?;
+                if let TType::Application(f) = &e_t {
+                    if !matches!(f, TypeFunc::Record(_)) {
+                        return self.error(
+                            label.position,
+                            format!(
+                                "`{}` is not a record, it has no field '{}'",
+                                e_t,
+                                label.text()
+                            ),
+                        );
+                    }
+                }
+                let field = self.variable();
+                let rest = self.variable();
+                let record = TType::Application(TypeFunc::Record(Box::new(row_extend(
+                    &label.text(),
+                    field.clone(),
+                    rest,
+                ))));
+                let s2 = self.unify(label.position, &e_t, &record)?;
+                Ok((s2.apply(&s1), s2.apply(&field)))
+            }
+            Ast::If(c, t, e) => {
+                let (s1, c_t) = self.w(ctx, c)?;
+                let s2 = self.unify(c.position(), &TType::Application(TypeFunc::Bool), &c_t)?;
+                let s = s2.apply(&s1);
+
+                let (s3, t_t) = self.w(&s.apply(ctx), t)?;
+                let s = s3.apply(&s);
+
+                let (s4, e_t) = self.w(&s.apply(ctx), e)?;
+                let s5 = self.unify(e.position(), &s4.apply(&t_t), &e_t)?;
+
+                Ok((s5.apply(&s4.apply(&s)), s5.apply(&e_t)))
+            }
+            Ast::Unit(_) => Ok((Substitution::new(), TType::Application(TypeFunc::Unit))),
             Ast::Err => Err(()),
+            Ast::Interpolation(parts) => {
+                // Any value can be spliced into a string
+                let mut s = Substitution::new();
+                for part in parts {
+                    let (s1, _) = self.w(&s.apply(ctx), part)?;
+                    s = s1.apply(&s);
+                }
+                Ok((s, TType::Application(TypeFunc::String)))
+            }
+            Ast::UnaryOp(op, e) => {
+                // -: number -> number where number is int or float, !: bool -> bool
+                let t = match op.kind {
+                    TokenKind::Bang => TType::Application(TypeFunc::Bool),
+                    _ => {
+                        let v = self.variable();
+                        self.numeric.push((v.clone(), op.clone()));
+                        v
+                    }
+                };
+                let (s1, e_t) = self.w(ctx, e)?;
+                let s2 = self.unify(e.position(), &t, &e_t)?;
+                Ok((s2.apply(&s1), s2.apply(&t)))
+            }
             Ast::BinaryOp(t, e1, e2) => {
                 // Built an ast where the operator is a function application and type check that.
                 // So "1 + 2" -> (+ 1) 2
@@ -217,33 +1224,610 @@ impl TypeChecker {
     }
 }
 
+/// A row with a `label` field in front of the fields of `rest`.
+fn row_extend(label: &str, field: TType, rest: TType) -> TType {
+    TType::Application(TypeFunc::RowExtend {
+        label: label.to_string(),
+        field: Box::new(field),
+        rest: Box::new(rest),
+    })
+}
+
+/// The variable a row ends in, if it isn't closed.
+fn row_tail(row: &TType) -> Option<TType> {
+    match row {
+        TType::Application(TypeFunc::RowExtend { rest, .. }) => row_tail(rest),
+        TType::Variable(_) => Some(row.clone()),
+        _ => None,
+    }
+}
+
+/// The type of a literal.
+fn literal_type(kind: &TokenKind) -> Option<TType> {
+    match kind {
+        TokenKind::LiteralInt(_) => Some(TType::Application(TypeFunc::Int)),
+        TokenKind::LiteralFloat(_) => Some(TType::Application(TypeFunc::Float)),
+        TokenKind::LiteralBool(_) => Some(TType::Application(TypeFunc::Bool)),
+        TokenKind::LiteralString(_) => Some(TType::Application(TypeFunc::String)),
+        _ => None,
+    }
+}
+
+/// A pattern as far as checking which values it matches is concerned.
+#[derive(Clone, PartialEq, Debug)]
+enum Shape {
+    /// Matches every value
+    Any,
+    /// Matches the values with this head whose fields match the shapes
+    Head(Head, Vec<Shape>),
+}
+
+/// What sets the values of a type apart from each other at the top level.
+#[derive(Clone, PartialEq, Debug)]
+enum Head {
+    Constructor(String),
+    /// Every tuple has the same head, only its elements can differ
+    Tuple,
+    Bool(bool),
+    /// An int, float or string
+    Literal(String),
+}
+
+/// What the first column of a pattern matrix covers.
+enum Column {
+    /// Every value, these are all the heads of its type and how many fields each has
+    Complete(Vec<(Head, usize)>),
+    /// Not every value, with a head that is missing from it if there is a way to name one
+    Incomplete(Option<(Head, usize)>),
+}
+
+impl From<&Pattern> for Shape {
+    fn from(pattern: &Pattern) -> Self {
+        match pattern {
+            Pattern::Name(_) | Pattern::Wildcard(_) => Shape::Any,
+            Pattern::Literal(t) => match t.kind {
+                TokenKind::LiteralBool(b) => Shape::Head(Head::Bool(b), Vec::new()),
+                _ => Shape::Head(Head::Literal(t.text()), Vec::new()),
+            },
+            Pattern::Tuple(elements, _) => {
+                Shape::Head(Head::Tuple, elements.iter().map(Shape::from).collect())
+            }
+            Pattern::Constructor(name, fields, _) => Shape::Head(
+                Head::Constructor(name.text()),
+                fields.iter().map(Shape::from).collect(),
+            ),
+        }
+    }
+}
+
+impl Display for Shape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |fields: &[Shape]| {
+            fields
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        match self {
+            Shape::Any => write!(f, "_"),
+            Shape::Head(Head::Tuple, fields) => write!(f, "({})", list(fields)),
+            Shape::Head(Head::Bool(b), _) => write!(f, "{}", b),
+            Shape::Head(Head::Literal(l), _) => write!(f, "{}", l),
+            Shape::Head(Head::Constructor(name), fields) if fields.is_empty() => {
+                write!(f, "{}", name)
+            }
+            Shape::Head(Head::Constructor(name), fields) => write!(f, "{}({})", name, list(fields)),
+        }
+    }
+}
+
+/// The rows that match a value with this head, with their first column replaced by the fields
+/// of the head.
+fn specialize(rows: &[Vec<Shape>], head: &Head, arity: usize) -> Vec<Vec<Shape>> {
+    rows.iter()
+        .filter_map(|row| {
+            let (first, rest) = row.split_first()?;
+            let fields = match first {
+                Shape::Any => vec![Shape::Any; arity],
+                Shape::Head(h, fields) if h == head => fields.clone(),
+                Shape::Head(_, _) => return None,
+            };
+            Some(fields.into_iter().chain(rest.iter().cloned()).collect())
+        })
+        .collect()
+}
+
+/// The rows that match any value in their first column, without that column.
+fn default(rows: &[Vec<Shape>]) -> Vec<Vec<Shape>> {
+    rows.iter()
+        .filter(|row| row.first() == Some(&Shape::Any))
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+/// Tarjan's algorithm. Given the edges going out of every node, this finds the groups of nodes
+/// that can all reach each other. Groups come out in dependency order, so a group is only
+/// listed after every group it has an edge to.
+fn strongly_connected_components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct State<'a> {
+        edges: &'a [Vec<usize>],
+        counter: usize,
+        index: Vec<Option<usize>>,
+        low_link: Vec<usize>,
+        stack: Vec<usize>,
+        on_stack: Vec<bool>,
+        components: Vec<Vec<usize>>,
+    }
+
+    fn connect(state: &mut State, node: usize) {
+        state.index[node] = Some(state.counter);
+        state.low_link[node] = state.counter;
+        state.counter += 1;
+        state.stack.push(node);
+        state.on_stack[node] = true;
+
+        for &next in &state.edges[node] {
+            match state.index[next] {
+                None => {
+                    connect(state, next);
+                    state.low_link[node] = state.low_link[node].min(state.low_link[next]);
+                }
+                Some(i) if state.on_stack[next] => {
+                    state.low_link[node] = state.low_link[node].min(i);
+                }
+                Some(_) => {}
+            }
+        }
+
+        if Some(state.low_link[node]) == state.index[node] {
+            let mut component = Vec::new();
+            while let Some(n) = state.stack.pop() {
+                state.on_stack[n] = false;
+                component.push(n);
+                if n == node {
+                    break;
+                }
+            }
+            component.reverse();
+            state.components.push(component);
+        }
+    }
+
+    let mut state = State {
+        edges,
+        counter: 0,
+        index: vec![None; edges.len()],
+        low_link: vec![0; edges.len()],
+        stack: Vec::new(),
+        on_stack: vec![false; edges.len()],
+        components: Vec::new(),
+    };
+
+    for node in 0..edges.len() {
+        if state.index[node].is_none() {
+            connect(&mut state, node);
+        }
+    }
+
+    state.components
+}
+
 pub type Input = crate::phase::ast_builder::Output;
-pub type Output = HashMap<PathBuf, TType>;
+pub type Output = HashMap<PathBuf, Context>;
 impl Phase<Input, Output> for TypeChecker {
     fn new() -> Self {
-        TypeChecker::new(&PathBuf::new())
+        TypeChecker::with_builtins(Builtins::default())
     }
 
-    fn run(self: &mut Self, _config: &crate::config::Config, input: &Input) -> PhaseResult<Output> {
+    fn run(&mut self, _config: &crate::config::Config, input: &Input) -> PhaseResult<Output> {
         let mut out = HashMap::new();
         let mut errs = Vec::new();
+        // A module can fail without an error of its own, when it contains a tree the parser
+        // already reported
+        let mut failed = false;
 
-        for (source_path, ast) in input {
-            *self = TypeChecker::new(source_path);
-            match self.w(&Context::new(), ast) {
+        // The prelude is checked first, so what it defines is in scope in every other module
+        let mut base = self.builtins.types();
+        let (prelude, modules): (Vec<_>, Vec<_>) = input
+            .iter()
+            .partition(|(path, _)| prelude::is_prelude(path));
+        for (source_path, program) in prelude.into_iter().chain(modules) {
+            *self = TypeChecker::new(source_path, self.builtins.clone());
+            match self.check_program(program, &base) {
                 Err(_) => {
+                    failed = true;
                     errs.append(&mut self.errors);
                 }
-                Ok((_, t)) => {
-                    out.insert(source_path.clone(), t);
+                Ok(ctx) => {
+                    // Only warnings are left
+                    errs.append(&mut self.errors);
+                    if prelude::is_prelude(source_path) {
+                        base = ctx.clone();
+                    }
+                    out.insert(source_path.clone(), ctx);
                 }
             };
         }
 
-        if !errs.is_empty() {
+        if failed || errs.iter().any(|e| e.severity == Severity::Error) {
             PhaseResult::Err(errs)
+        } else if !errs.is_empty() {
+            PhaseResult::SoftErr(out, errs)
         } else {
             PhaseResult::Ok(out)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builtins::Builtins,
+        config::Config,
+        message::Severity,
+        phase::{
+            testing::{build_past_errors, build_with, check, type_of},
+            type_checker::TypeChecker,
+            Phase, PhaseResult,
+        },
+    };
+
+    /// The message and column of each error the type checker reports.
+    fn errors(source: &str) -> Vec<(String, usize)> {
+        check(source)
+            .into_iter()
+            .map(|m| (m.content.message, m.position.column))
+            .collect()
+    }
+
+    #[test]
+    fn let_bindings_are_generalized() {
+        let source = "def main() { let id = \\x -> x; (id(1), id(\"a\")) }";
+        assert_eq!(type_of(source, "main"), "() -> (int, string)");
+    }
+
+    #[test]
+    fn ifs_need_a_bool_condition_and_matching_branches() {
+        assert_eq!(
+            type_of("def main() { if 1 < 2 then \"a\" else \"b\" }", "main"),
+            "() -> string"
+        );
+        assert_eq!(
+            errors("def main() { if 1 then 2 else 3 }"),
+            [("expected `bool` but found `int`".to_string(), 16)]
+        );
+        assert_eq!(
+            errors("def main() { if true then 1 else \"a\" }"),
+            [("expected `int` but found `string`".to_string(), 33)]
+        );
+    }
+
+    #[test]
+    fn programs_with_syntax_errors_are_rejected() {
+        let program = build_past_errors(
+            "
+            def f(x: int): int { x + }
+            def main() { 1 + true }",
+        );
+        let result =
+            TypeChecker::with_builtins(Builtins::default()).run(&Config::default(), &program);
+        assert!(matches!(result, PhaseResult::Err(_)));
+    }
+
+    #[test]
+    fn definitions_defined_twice_are_reported_at_the_name() {
+        assert_eq!(
+            errors("def f() { 1 }\ndef f() { 2 }\ndef main() { f }"),
+            [("'f' is defined more than once".to_string(), 4)]
+        );
+    }
+
+    #[test]
+    fn annotation_mismatches_point_at_the_annotation() {
+        let source = "def f(x: int): string { x }\ndef main() { f(1) }";
+        let messages = check(source);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0].content.message,
+            "expected `string` but found `int`"
+        );
+        let position = messages[0].position;
+        assert_eq!(&source[position.begin..position.end], "string");
+
+        assert_eq!(
+            errors("def f(x: bool): int { x }\ndef main() { 1 }"),
+            [("expected `int` but found `bool`".to_string(), 16)]
+        );
+    }
+
+    #[test]
+    fn definitions_without_parameters_take_unit() {
+        assert_eq!(
+            type_of("def main() { (read_line, read_line()) }", "main"),
+            "() -> (() -> string, string)"
+        );
+        assert_eq!(
+            errors("def f(): string { 1 }\ndef main() { f() }"),
+            [("expected `string` but found `int`".to_string(), 9)]
+        );
+        let messages = check("def f(): int { 1 }\ndef main() { f(1) }");
+        assert_eq!(messages[0].content.message, "expected `()` but found `int`");
+    }
+
+    #[test]
+    fn misused_parameters_are_blamed_where_they_are_used() {
+        let source = "def f(x: int) { x ++ \"a\" }\ndef main() { f(1) }";
+        let messages = check(source);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0].content.message,
+            "expected `string` but found `int`"
+        );
+        let position = messages[0].position;
+        assert_eq!(&source[position.begin..position.end], "x");
+
+        for (source, blamed) in [
+            (
+                "def f((a, b): (int, bool)) { b && a }\ndef main() { 1 }",
+                "a",
+            ),
+            ("def main() { (\\(x: int) -> !x)(1) }", "x"),
+        ] {
+            let position = check(source)[0].position;
+            assert_eq!(&source[position.begin..position.end], blamed);
+        }
+    }
+
+    #[test]
+    fn unknown_type_names_are_reported() {
+        assert_eq!(
+            errors("def f(x: Strnig) { x }\ndef main() { 1 }"),
+            [("unknown type 'Strnig'".to_string(), 9)]
+        );
+        assert_eq!(
+            errors("def f(x: Option(int)): int { 1 }\ndef main() { 1 }"),
+            [("unknown type 'Option'".to_string(), 9)]
+        );
+    }
+
+    #[test]
+    fn declared_return_types_are_checked_against_what_was_inferred() {
+        assert_eq!(
+            errors("def g(x: a, y: b): a { y }\ndef main() { 1 }"),
+            [(
+                "'a' and 'b' stand for any two types, but they have to be the same".to_string(),
+                15
+            )]
+        );
+        assert_eq!(
+            errors("def id(x: a): a { 1 }\ndef main() { 1 }"),
+            [(
+                "'a' stands for any type, but it has to be `int`".to_string(),
+                10
+            )]
+        );
+    }
+
+    #[test]
+    fn comparisons_are_bool_and_logic_needs_bools() {
+        assert_eq!(
+            type_of("def main() { 1.5 <= 2.0 && 3 % 2 != 0 }", "main"),
+            "() -> bool"
+        );
+        assert_eq!(
+            errors("def main() { 1 || true }"),
+            [("expected `bool` but found `int`".to_string(), 13)]
+        );
+    }
+
+    #[test]
+    fn declared_operators_have_the_type_of_their_definition() {
+        let source = "
+            infixl 4 ~~
+            def (~~)(a: int, b: int): bool { a == b }
+            def main() { \"a\" ~~ 2 }";
+        assert_eq!(
+            errors(source),
+            [("expected `int` but found `string`".to_string(), 25)]
+        );
+    }
+
+    #[test]
+    fn negation_needs_a_number_and_not_a_bool() {
+        assert_eq!(
+            type_of("def main() { (-1, -2.5, !true) }", "main"),
+            "() -> (int, float, bool)"
+        );
+        assert_eq!(
+            errors("def main() { -true }"),
+            [(
+                "'-' works on `int` and `float`, but not `bool`".to_string(),
+                13
+            )]
+        );
+        assert_eq!(
+            errors("def main() { !1 }"),
+            [("expected `bool` but found `int`".to_string(), 14)]
+        );
+    }
+
+    #[test]
+    fn only_strings_are_concatenated() {
+        assert_eq!(
+            errors("def main() { \"a\" ++ 1 }"),
+            [("expected `string` but found `int`".to_string(), 20)]
+        );
+    }
+
+    #[test]
+    fn ints_and_floats_do_not_mix() {
+        assert_eq!(
+            type_of("def main() { ((\\x -> x + x)(2.5), 7 % 2) }", "main"),
+            "() -> (float, int)"
+        );
+        assert_eq!(
+            errors("def main() { 1 + 1.0 }"),
+            [("expected `int` but found `float`".to_string(), 17)]
+        );
+    }
+
+    #[test]
+    fn numeric_functions_work_on_ints_and_floats() {
+        let source = "def main() { let f = \\x -> x + x; (f(1), f(2.5)) }";
+        assert_eq!(type_of(source, "main"), "() -> (int, float)");
+
+        let source = "def twice(x: a): a { x + x }\ndef main() { (twice(1), twice(2.5)) }";
+        assert_eq!(type_of(source, "main"), "() -> (int, float)");
+
+        let messages = check("def main() { let f = \\x -> x + x; f(\"a\") }");
+        assert_eq!(
+            messages[0].content.message,
+            "'f' works on `int` and `float`, but not `string`"
+        );
+    }
+
+    #[test]
+    fn constructors_are_polymorphic_functions() {
+        let source = "
+            type Option(a) = None | Some(a)
+            def wrap(x: a) { Some(x) }
+            def main() { (wrap(1), wrap(\"a\")) }";
+        assert_eq!(
+            type_of(source, "main"),
+            "() -> (Option(int), Option(string))"
+        );
+
+        let source = "
+            type Option(a) = None | Some(a)
+            def main() { if true then Some(1) else Some(\"a\") }";
+        assert_eq!(
+            errors(source),
+            [("expected `int` but found `string`".to_string(), 51)]
+        );
+    }
+
+    #[test]
+    fn tuple_patterns_must_fit_the_tuple() {
+        assert_eq!(
+            type_of(
+                "def main() { let (a, (b, c)) = (1, (\"x\", true)); (c, b, a) }",
+                "main"
+            ),
+            "() -> (bool, string, int)"
+        );
+        assert_eq!(
+            errors("def main() { let (a, b) = (1, 2, 3); a }"),
+            [(
+                "this pattern takes apart 2 values, but `(int, int, int)` has 3".to_string(),
+                17
+            )]
+        );
+        assert_eq!(
+            errors("def f((a, b): (int, int)) { a }\ndef main() { f(1) }"),
+            [("expected `(int, int)` but found `int`".to_string(), 15)]
+        );
+    }
+
+    #[test]
+    fn negative_literal_patterns_have_the_type_of_their_number() {
+        let source = "def sign(n: int) { match n { -1 -> \"minus\", _ -> \"other\" } }
+            def main() { sign(-1) }";
+        assert_eq!(type_of(source, "sign"), "int -> string");
+
+        let source = "def main() { match 1 { -2.5 -> 0, _ -> 1 } }";
+        let messages = check(source);
+        assert_eq!(messages.len(), 1);
+        let position = messages[0].position;
+        assert_eq!(&source[position.begin..position.end], "-2.5");
+    }
+
+    #[test]
+    fn matches_are_checked_for_missing_and_unreachable_arms() {
+        let source = "
+            type Option(a) = None | Some(a)
+            def f(o: Option((bool, int))): int {
+                match o {
+                    Some((true, n)) -> n,
+                    None -> 0,
+                    Some((true, 1)) -> 1,
+                }
+            }
+            def main() { f(None) }";
+        let messages = check(source)
+            .into_iter()
+            .map(|m| (m.severity, m.content.message, m.position.line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    Severity::Warning,
+                    "this arm is never reached".to_string(),
+                    6
+                ),
+                (
+                    Severity::Warning,
+                    "this match does not handle `Some((false, _))`".to_string(),
+                    3
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_on_bools_and_literals_need_a_catch_all() {
+        let warnings = |source: &str| {
+            check(source)
+                .into_iter()
+                .map(|m| (m.severity, m.content.message))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            warnings("def main() { match 1 < 2 { true -> 1, false -> 0 } }"),
+            []
+        );
+        assert_eq!(
+            warnings("def main() { match 3 { 0 -> 1, 1 -> 0 } }"),
+            [(
+                Severity::Warning,
+                "this match does not handle `_`".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn record_fields_are_found_in_any_record_that_has_them() {
+        let source = "
+            def main() {
+                let age = \\r -> r.age;
+                let older = \\r -> { r | age: r.age + 1 };
+                (age({ age: 1 }), older({ age: 2, legs: 4 }).legs)
+            }";
+        assert_eq!(type_of(source, "main"), "() -> (int, int)");
+        assert_eq!(
+            errors("def main() { let p = { name: \"x\" }; p.age }"),
+            [("`{ name: string }` has no field 'age'".to_string(), 38)]
+        );
+    }
+
+    #[test]
+    fn programs_see_the_prelude_unless_it_is_left_out() {
+        let source = "
+            def fold(s: string): string { s ++ \"!\" }
+            def main() { (fold(\"a\"), length([1])) }";
+        assert_eq!(type_of(source, "main"), "() -> (string, int)");
+
+        let config = Config {
+            prelude: false,
+            ..Config::default()
+        };
+        let program = build_with(&config, "def main() { length([]) }");
+        let PhaseResult::Err(errors) =
+            TypeChecker::with_builtins(Builtins::default()).run(&config, &program)
+        else {
+            panic!("program was not rejected");
+        };
+        assert_eq!(errors[0].content.message, "'length' is not defined here");
+    }
+}
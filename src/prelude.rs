@@ -0,0 +1,24 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::config::Config;
+
+/// The path the prelude is known by among the sources of a program.
+pub const PATH: &str = "prelude.sfl";
+
+/// The functions every program can use without defining them, written in SFL itself
+/// on top of the built-in list primitives.
+pub const SOURCE: &str = include_str!("prelude.sfl");
+
+/// Add the prelude to the sources of a program, unless it is turned off.
+pub fn add(config: &Config, sources: &mut HashMap<PathBuf, String>) {
+    if config.prelude {
+        sources.insert(PathBuf::from(PATH), SOURCE.to_string());
+    }
+}
+
+pub fn is_prelude(path: &Path) -> bool {
+    path == Path::new(PATH)
+}
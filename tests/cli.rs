@@ -0,0 +1,54 @@
+use std::{fs, path::PathBuf, process::Command};
+
+/// Run `sfl run` in a folder of its own with `source` as its 'main.sfl'.
+fn run(name: &str, source: &str) -> std::process::Output {
+    run_with(name, source, &[])
+}
+
+fn run_with(name: &str, source: &str, flags: &[&str]) -> std::process::Output {
+    let project = std::env::temp_dir().join(format!("sfl-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&project).unwrap();
+    fs::write(project.join("main.sfl"), source).unwrap();
+
+    let output = Command::new(PathBuf::from(env!("CARGO_BIN_EXE_sfl")))
+        .arg("run")
+        .args(flags)
+        .current_dir(&project)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&project).unwrap();
+    output
+}
+
+#[test]
+fn programs_with_errors_are_not_run() {
+    let output = run("unterminated", "def main() { \"abc {1 + 2\" }");
+    assert!(!output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("abc 3"));
+
+    let output = run(
+        "missing-operand",
+        "def f(x: int): int { x + }\ndef main() { 1 + true }",
+    );
+    assert!(!output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("cannot apply"));
+}
+
+#[test]
+fn programs_without_errors_are_run() {
+    let output = run("valid", "def main() { 1 + 2 }");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+
+    let output = run("printing", "def main() { println(\"hi\") }");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hi\n");
+}
+
+#[test]
+fn dumps_go_to_stderr() {
+    let output = run_with("dump", "def main() { 1 + 2 }", &["--dump"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--- AST"));
+    assert!(stderr.contains("() -> int"));
+}